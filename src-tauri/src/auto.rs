@@ -1,14 +1,14 @@
-use std::cmp::max;
 use serde::Serialize;
 use uiautomation::UIElement;
 use uiautomation::actions::Window;
 use uiautomation::variants::Variant;
 use uiautomation::core::UIAutomation;
-use uiautomation::types::{UIProperty, TreeScope};
+use uiautomation::patterns::{UITextPattern, UIValuePattern};
+use uiautomation::types::{UIProperty, TreeScope, TextPatternRangeEndpoint, TextUnit};
 use clipboard::{ClipboardContext, ClipboardProvider};
 use uiautomation::controls::{ControlType, WindowControl};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 #[derive(Serialize)]
 pub struct WechatHistory {
     pub text: String,
@@ -16,6 +16,33 @@ pub struct WechatHistory {
     pub sender_type: String
 }
 
+// 微信输入框中的换行是\r
+fn normalize_newlines(text: String) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+// 换行规范化之后的prefix在原始草稿中占的字符数，TextPattern按原始内容移动光标。
+// 草稿不以prefix开头时返回None
+fn raw_prefix_len(raw: &str, prefix: &str) -> Option<usize> {
+    let mut chars = raw.chars().peekable();
+    let mut len = 0;
+    for expected in prefix.chars() {
+        let mut actual = chars.next()?;
+        len += 1;
+        if actual == '\r' {
+            if chars.peek() == Some(&'\n') {
+                chars.next();
+                len += 1;
+            }
+            actual = '\n';
+        }
+        if actual != expected {
+            return None;
+        }
+    }
+    Some(len)
+}
+
 #[derive(Debug)]
 pub struct UiAutoSession {
     automation: UIAutomation,
@@ -50,7 +77,7 @@ impl UiAutoSession {
         }
     }
 
-    pub fn wechat_content(&self) -> Result<Vec<WechatHistory>, String> {
+    pub fn wechat_content(&self, max_items: usize) -> Result<Vec<WechatHistory>, String> {
         let wechat = self.find_wechat_wnd();
         if wechat.is_err() { return Err(wechat.unwrap_err()); }
         
@@ -71,8 +98,8 @@ impl UiAutoSession {
         .find_all(TreeScope::Children, &list_item_cond) {
             let real_text_cond = self.automation.create_property_condition(
                 UIProperty::ControlType, Variant::from(0xC364), None).unwrap();
-            let msg_list_collect = msg_list.iter().skip(max(0, (
-                msg_list.len() as i32) - 20) as usize).map(|msg| {
+            let msg_list_collect = msg_list.iter().skip(
+                msg_list.len().saturating_sub(max_items)).map(|msg| {
                 let mut str_sender: String = String::new();
                 let mut str_content: String = String::new();
                 let msg_name = msg.get_name();
@@ -116,7 +143,111 @@ impl UiAutoSession {
         let wechat = self.find_wechat_wnd();
         if wechat.is_err() { return Err(wechat.unwrap_err()); }
         
-        let wechat = wechat.unwrap();
+        let (edit_box, send_button) = self.find_edit_box(wechat.unwrap())?;
+        self.paste_text(&edit_box, text, false)?;
+        if direct_send {
+            let _ = send_button.click().is_ok();
+        }
+        Ok(())
+    }
+
+    // 通过剪贴板把内容粘贴到输入框，replace为true时先全选，替换掉原有的内容
+    fn paste_text(&self, edit_box: &UIElement, text: String, replace: bool) -> Result<(), String> {
+        if !edit_box.has_keyboard_focus().unwrap() {
+            if edit_box.click().is_err() {
+                return Err(String::from("点击消息窗口失败，请检查微信窗口是否可见"));
+            }
+        }
+        if replace && edit_box.send_keys("{ctrl}A", 20).is_err() {
+            return Err(String::from("无法选中输入框中的内容，请稍后重试"));
+        }
+        let mut clip = ClipboardContext::new().unwrap();
+        let clip_backup = if let Ok(content) = clip.get_contents() 
+        { content } else { String::new() };
+
+        if clip.set_contents(text).is_err() {
+            return Err(String::from("无法复制消息，请稍后重试"));
+        }
+        if let Ok(()) = edit_box.send_keys("{ctrl}V", 20) {
+            let _ = clip.set_contents(clip_backup).is_ok();    
+            Ok(())
+        } else {
+            Err(String::from("无法复制消息，请稍后重试"))
+        }
+    }
+
+    // 读取输入框中尚未发送的草稿，优先使用ValuePattern，不支持时退回TextPattern
+    pub fn read_draft(&self) -> Result<String, String> {
+        let wechat = self.find_wechat_wnd()?;
+        let (edit_box, _) = self.find_edit_box(wechat)?;
+        Self::raw_draft(&edit_box).map(normalize_newlines)
+    }
+
+    // 未经换行规范化的草稿
+    fn raw_draft(edit_box: &UIElement) -> Result<String, String> {
+        edit_box.get_pattern::<UIValuePattern>().and_then(|pattern| pattern.get_value())
+            .or_else(|_| edit_box.get_pattern::<UITextPattern>()
+                .and_then(|pattern| pattern.get_document_range())
+                .and_then(|range| range.get_text(-1)))
+            .map_err(|_| String::from("无法读取输入框中的内容，请稍后重试"))
+    }
+
+    // 草稿中光标之前的内容。优先以选区的起点为准，其次是光标位置，都取不到时视为光标在末尾
+    pub fn draft_before_caret(&self) -> Result<String, String> {
+        let wechat = self.find_wechat_wnd()?;
+        let (edit_box, _) = self.find_edit_box(wechat)?;
+        let Ok(pattern) = edit_box.get_pattern::<UITextPattern>() else { return self.read_draft(); };
+        let caret = pattern.get_selection().ok().and_then(|ranges| ranges.into_iter().next())
+            .or_else(|| pattern.get_caret_range().ok().map(|(_, range)| range));
+        let prefix = pattern.get_document_range().and_then(|document| {
+            if let Some(caret) = &caret {
+                document.move_endpoint_by_range(TextPatternRangeEndpoint::End, caret, TextPatternRangeEndpoint::Start)?;
+            }
+            document.get_text(-1)
+        });
+        match prefix {
+            Ok(prefix) => Ok(normalize_newlines(prefix)),
+            Err(_) => Err(String::from("无法读取输入框中的内容，请稍后重试")),
+        }
+    }
+
+    // 在prefix之后插入补全的内容。工具窗口抢走焦点后输入框的光标位置不可靠，按prefix的长度重新放置光标
+    pub fn insert_at_caret(&self, prefix: &str, text: String) -> Result<(), String> {
+        let wechat = self.find_wechat_wnd()?;
+        let (edit_box, _) = self.find_edit_box(wechat)?;
+        let draft = Self::raw_draft(&edit_box)?;
+        let Some(offset) = raw_prefix_len(&draft, prefix) else {
+            return Err(String::from("输入框中的内容已经变化，请重新补全"));
+        };
+        if edit_box.set_focus().is_err() {
+            return Err(String::from("点击消息窗口失败，请检查微信窗口是否可见"));
+        }
+        let placed = edit_box.get_pattern::<UITextPattern>()
+            .and_then(|pattern| pattern.get_document_range())
+            .and_then(|range| {
+                range.move_endpoint_by_range(TextPatternRangeEndpoint::End, &range.clone(), TextPatternRangeEndpoint::Start)?;
+                range.move_text(TextUnit::Character, offset as i32)?;
+                range.select()
+            });
+        // 不支持TextPattern时，只有光标本来就在末尾才能继续
+        if placed.is_err() && (offset != draft.chars().count() || edit_box.send_keys("{ctrl}{end}", 20).is_err()) {
+            return Err(String::from("无法定位输入框中的光标，请稍后重试"));
+        }
+        self.paste_text(&edit_box, text, false)
+    }
+
+    // 用改写后的内容替换草稿。输入框的内容与original不一致时说明草稿已被修改，不做替换
+    pub fn replace_draft(&self, original: &str, text: String) -> Result<(), String> {
+        if self.read_draft()?.trim() != original.trim() {
+            return Err(String::from("输入框中的内容已经变化，请重新润色"));
+        }
+        let wechat = self.find_wechat_wnd()?;
+        let (edit_box, _) = self.find_edit_box(wechat)?;
+        self.paste_text(&edit_box, text, true)
+    }
+
+    // 返回当前聊天的输入框和发送按钮
+    fn find_edit_box(&self, wechat: UIElement) -> Result<(UIElement, UIElement), String> {
         let walker = self.automation.create_tree_walker().unwrap();
         let edit_cond = self.automation.create_property_condition(
             UIProperty::ControlType, Variant::from(0xC354), None).unwrap();
@@ -131,32 +262,42 @@ impl UiAutoSession {
         .and_then(|node| walker.get_parent(&node))
         .and_then(|parent| parent.find_first(TreeScope::Descendants, &edit_cond)) 
         {
-            if !edit_box.has_keyboard_focus().unwrap() {
-                if edit_box.click().is_err() {
-                    return Err(String::from("点击消息窗口失败，请检查微信窗口是否可见"));
-                }
-            }
-            let mut clip = ClipboardContext::new().unwrap();
-            let clip_backup = if let Ok(content) = clip.get_contents() 
-            { content } else { String::new() };
-
-            if clip.set_contents(text).is_err() {
-                return Err(String::from("无法复制消息，请稍后重试"));
-            }
-            if let Ok(()) = edit_box.send_keys("{ctrl}V", 20) {
-                let _ = clip.set_contents(clip_backup).is_ok();    
-                if !direct_send {
-                    Ok(())
-                } else {
-                    let _ = send_button.click().is_ok();
-                    Ok(())
-                }
-            } else {
-                return Err(String::from("无法复制消息，请稍后重试"));
-            }
+            Ok((edit_box, send_button))
         } else {
             Err(String::from("无法定位到消息框，请稍后重试"))
         }
+    }
 
+    // 微信输入框的名称就是当前聊天的标题（联系人备注或群名）
+    pub fn chat_title(&self) -> Result<String, String> {
+        let wechat = self.find_wechat_wnd()?;
+        let (edit_box, _) = self.find_edit_box(wechat)?;
+        match edit_box.get_name() {
+            Ok(title) if !title.is_empty() => Ok(title),
+            _ => Err(String::from("无法获取当前聊天的名称")),
+        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_newlines, raw_prefix_len};
+
+    #[test]
+    fn test_normalize_newlines() {
+        assert_eq!(normalize_newlines(String::from("你好\r\n明天\r见")), "你好\n明天\n见");
+        assert_eq!(normalize_newlines(String::from("你好")), "你好");
+    }
+
+    #[test]
+    fn test_raw_prefix_len() {
+        // \r\n在规范化后只剩一个字符，光标仍要越过两个字符
+        assert_eq!(raw_prefix_len("你好\r\n明天见", "你好\n明"), Some(5));
+        assert_eq!(raw_prefix_len("你好\r明天见", "你好\n明"), Some(4));
+        assert_eq!(raw_prefix_len("你好\r\n", "你好\n"), Some(4));
+        assert_eq!(raw_prefix_len("明天见", ""), Some(0));
+        assert_eq!(raw_prefix_len("明天见", "明天见"), Some(3));
+        assert_eq!(raw_prefix_len("明天见", "后天"), None);
+        assert_eq!(raw_prefix_len("明天", "明天见"), None);
+    }
+}
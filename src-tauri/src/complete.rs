@@ -0,0 +1,56 @@
+// 续写：以输入框中光标之前的内容为开头，给出几种接着往下写的方式，选中后插入到光标处
+use crate::provider::ChatPrompt;
+use crate::suggest;
+
+pub const COMPLETION_COUNT: usize = 3;
+
+// 要求模型原样重复草稿再接着写，比只输出续写部分更容易处理英文的空格和没写完的单词
+pub fn completion_prompt(nick: &str, prefix: &str) -> ChatPrompt {
+    ChatPrompt {
+        nick: String::from(nick),
+        bot_name: String::from("续写助手"),
+        system: format!("你负责帮{}把写了一半的微信消息补充完整，语气和语言与已经写好的部分保持一致。", nick),
+        history: Vec::new(),
+        instruction: format!("我已经写好的开头是：\n{}\n请结合上述聊天记录给出{}种不同的续写，每条都以上面的开头原样起始，\
+接着写完这条消息，不要改动开头。请以JSON格式输出，格式为{{\"suggestions\": [\"续写1\", \"续写2\"]}}，\
+不要输出JSON之外的任何内容。", prefix, COMPLETION_COUNT),
+        json_output: true,
+    }
+}
+
+// 去掉模型重复的开头，只保留需要插入的部分。没有以开头起始的续写无法确定插入的内容，直接丢弃
+pub fn parse_completions(content: &str, prefix: &str) -> Vec<String> {
+    let head = prefix.trim_end();
+    let mut completions: Vec<String> = Vec::new();
+    for item in suggest::parse_suggestions(content) {
+        let Some(rest) = item.strip_prefix(head) else { continue; };
+        let rest = if head.len() < prefix.len() { rest.trim_start() } else { rest };
+        if !rest.trim().is_empty() && !completions.iter().any(|completion| completion == rest) {
+            completions.push(String::from(rest));
+        }
+    }
+    completions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{completion_prompt, parse_completions};
+
+    #[test]
+    fn test_prompt() {
+        let prompt = completion_prompt("小明", "周末要不要一起");
+        assert!(prompt.instruction.starts_with("我已经写好的开头是：\n周末要不要一起\n请结合上述聊天记录给出3种不同的续写"));
+        assert!(prompt.json_output);
+    }
+
+    #[test]
+    fn test_parse() {
+        let content = r#"{"suggestions": ["周末要不要一起去爬山？", "周末要不要一起吃个饭", "要不要一起看电影", "周末要不要一起去爬山？"]}"#;
+        assert_eq!(parse_completions(content, "周末要不要一起"), vec!["去爬山？", "吃个饭"]);
+
+        let content = r#"["See you tomorrow", "See you soon!", "See you"]"#;
+        assert_eq!(parse_completions(content, "See you "), vec!["tomorrow", "soon!"]);
+        assert_eq!(parse_completions(content, "See you"), vec![" tomorrow", " soon!"]);
+        assert_eq!(parse_completions(r#"["See you tomorrow"]"#, "See you tomor"), vec!["row"]);
+    }
+}
@@ -1,6 +1,12 @@
-use std::vec::Vec;
+use serde_json::Value;
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
+use crate::models::ModelSpec;
+use crate::persona::{self, Persona};
+use crate::suggest::SuggestionConfig;
+use crate::postprocess::{self, Processor};
+use crate::safety::SafetyConfig;
+use crate::translate::TranslationConfig;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ModelConfig {
@@ -9,49 +15,120 @@ pub struct ModelConfig {
     pub temperature: i32,
     pub api_token: String,
     pub api_group: String,
+    #[serde(default)]
+    pub base_url: String,
+    // 透传给模型的额外参数，如Ollama的num_ctx
+    #[serde(default)]
+    pub options: HashMap<String, Value>,
+    // 是否使用流式输出，部分兼容服务不支持时可以关闭
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    // 以下为可选的采样参数，未设置时使用模型注册表中的默认值
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        ModelConfig {
+            name: String::new(),
+            provider: String::new(),
+            temperature: 0,
+            api_token: String::new(),
+            api_group: String::new(),
+            base_url: String::new(),
+            options: HashMap::new(),
+            stream: default_stream(),
+            max_tokens: None,
+            top_p: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            stop: Vec::new(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AppConfig {
     pub wechat_nick: String,
     pub model: ModelConfig,
+    // 主模型因网络、额度或服务端问题失败时，按顺序尝试的备用模型
+    #[serde(default)]
+    pub fallbacks: Vec<ModelConfig>,
     pub hot_key: String,
+    // 唤醒工具窗口并直接总结对话的快捷键，为空时不注册
+    #[serde(default)]
+    pub digest_hot_key: String,
+    // 自定义模型，会覆盖同名的内置模型
+    #[serde(default)]
+    pub models: Vec<ModelSpec>,
+    #[serde(default)]
+    pub summary: SummaryConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub budget: UsageBudget,
+    // 可选的回复人设，为空时只使用默认的提示词
+    #[serde(default = "persona::default_personas")]
+    pub personas: Vec<Persona>,
+    // 每次给出的建议条数和长短比例
+    #[serde(default)]
+    pub suggestion: SuggestionConfig,
+    // 建议的后处理步骤，按顺序执行
+    #[serde(default = "postprocess::default_pipeline")]
+    pub postprocess: Vec<Processor>,
+    // 发送前的安全检查
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    // 对话不是中文时附上建议的中文翻译
+    #[serde(default)]
+    pub translation: TranslationConfig,
 }
 
-#[derive(Serialize, Debug)]
-pub struct ApiRequest<'a, T> {
-    pub model: String,
-    pub temperature: f32,
-    pub messages: &'a Vec<T>,
-    pub tokens_to_generate: i32,
-    pub reply_constraints: ReplyConstraints<'a>,
-    pub bot_setting: Vec<&'a HashMap<&'a str, &'a str>>,
-}
-
-#[derive(Serialize, Debug)]
-pub struct ReplyConstraints<'a> {
-    pub sender_type: &'a str,
-    pub sender_name: &'a str,
-}
-
-
-impl<'a> ReplyConstraints<'a> {
-    pub fn new_minimax(bot_name: &'a str) -> Self {
-        ReplyConstraints {
-            sender_type: "BOT",
-            sender_name: bot_name
-        }
-    }
+// token用量上限，为空表示不限制
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UsageBudget {
+    #[serde(default)]
+    pub daily_tokens: Option<u64>,
+    #[serde(default)]
+    pub monthly_tokens: Option<u64>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct ApiResponseBase {
-    pub status_code: i32,
-    pub status_msg: String
+// 访问模型接口的网络设置，适用于需要经过代理或HTTPS解密网关的环境
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NetworkConfig {
+    // 代理地址，如http://10.0.0.1:8080或socks5://127.0.0.1:1080，留空时使用系统代理
+    #[serde(default)]
+    pub proxy: String,
+    #[serde(default)]
+    pub proxy_username: String,
+    #[serde(default)]
+    pub proxy_password: String,
+    // 不经过代理的主机，如localhost、*.corp.com、10.0.0.0/8。只对上面填写的代理生效，使用系统代理时按系统设置的例外处理
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+    // 额外信任的根证书文件（PEM格式）
+    #[serde(default)]
+    pub ca_certs: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct ApiResponse {
-    pub reply: String,
-    pub base_resp: ApiResponseBase
+// 超出上下文长度的早期聊天记录压缩成摘要，model为空时使用主模型，建议配置更便宜的模型
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SummaryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub model: Option<ModelConfig>,
 }
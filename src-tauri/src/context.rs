@@ -0,0 +1,146 @@
+// 按token预算挑选聊天记录：从最新的消息往前填充，放不下的早期消息可以压缩成滚动摘要
+use crate::auto::WechatHistory;
+use crate::models::ModelSpec;
+use crate::provider::ChatPrompt;
+
+// 每条消息除正文外的固定开销，如角色和消息分隔符
+const MESSAGE_OVERHEAD: usize = 4;
+// 为摘要预留的token数，同时也是摘要请求的最大输出长度
+pub const SUMMARY_TOKENS: usize = 300;
+// 用于判断是否还是同一段对话的消息数
+const SUMMARY_TAIL: usize = 3;
+
+pub trait Tokenizer: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+}
+
+// 按字符估算：中文等非ASCII字符每个约1个token，ASCII字符每4个约1个token
+pub struct CharTokenizer;
+
+impl Tokenizer for CharTokenizer {
+    fn count(&self, text: &str) -> usize {
+        let ascii = text.chars().filter(|c| c.is_ascii()).count();
+        text.chars().count() - ascii + ascii.div_ceil(4)
+    }
+}
+
+// 目前只有按字符估算的实现，接入模型专用的分词器后再改为按模型选择
+pub fn tokenizer() -> Box<dyn Tokenizer> {
+    Box::new(CharTokenizer)
+}
+
+pub fn message_tokens(tokenizer: &dyn Tokenizer, message: &WechatHistory) -> usize {
+    tokenizer.count(&message.sender_name) + tokenizer.count(&message.text) + MESSAGE_OVERHEAD
+}
+
+// 扣除输出长度和系统提示词、指令后，留给聊天记录的预算
+pub fn history_budget(tokenizer: &dyn Tokenizer, spec: &ModelSpec, max_tokens: u32, prompt: &ChatPrompt) -> usize {
+    let fixed = tokenizer.count(&prompt.system) + tokenizer.count(&prompt.instruction) + MESSAGE_OVERHEAD * 2;
+    (spec.context_length.saturating_sub(max_tokens) as usize).saturating_sub(fixed)
+}
+
+// 返回分界位置，之后的消息放得进预算；最新的一条消息无论多长都会保留
+pub fn split_history(history: &[WechatHistory], budget: usize, tokenizer: &dyn Tokenizer) -> usize {
+    let mut used = 0;
+    let mut split = history.len();
+    for (index, message) in history.iter().enumerate().rev() {
+        used += message_tokens(tokenizer, message);
+        if used > budget && split < history.len() {
+            break;
+        }
+        split = index;
+    }
+    split
+}
+
+fn fingerprint(messages: &[WechatHistory]) -> Vec<(String, String)> {
+    messages.iter().map(|message| (message.sender_name.clone(), message.text.clone())).collect()
+}
+
+// 滚动摘要：记住摘要覆盖的最后几条消息，下次只需要把新溢出的消息合并进已有的摘要
+#[derive(Debug, Clone, Default)]
+pub struct RollingSummary {
+    pub text: String,
+    tail: Vec<(String, String)>,
+}
+
+impl RollingSummary {
+    pub const fn new() -> Self {
+        RollingSummary { text: String::new(), tail: Vec::new() }
+    }
+
+    // 返回已有的摘要和尚未被摘要覆盖的消息，找不到上次覆盖的消息时说明换了聊天，需要重新摘要
+    pub fn pending<'a>(&self, overflow: &'a [WechatHistory]) -> (Option<&str>, &'a [WechatHistory]) {
+        if !self.tail.is_empty() && overflow.len() >= self.tail.len() {
+            let found = (0..=overflow.len() - self.tail.len()).rev()
+                .find(|&start| fingerprint(&overflow[start..start + self.tail.len()]) == self.tail);
+            if let Some(start) = found {
+                return (Some(self.text.as_str()), &overflow[start + self.tail.len()..]);
+            }
+        }
+        (None, overflow)
+    }
+
+    pub fn update(&mut self, text: &str, overflow: &[WechatHistory]) {
+        self.text = String::from(text.trim());
+        self.tail = fingerprint(&overflow[overflow.len().saturating_sub(SUMMARY_TAIL)..]);
+    }
+}
+
+pub fn summary_prompt(nick: &str, previous: Option<&str>, messages: &[WechatHistory]) -> ChatPrompt {
+    let instruction = match previous {
+        Some(previous) => format!("此前的对话摘要：{}\n请把以上新的对话记录合并进摘要，输出更新后的摘要。", previous),
+        None => String::from("请概括以上对话记录。"),
+    };
+    ChatPrompt {
+        nick: String::from(nick),
+        bot_name: String::from("对话摘要助手"),
+        system: format!("你负责压缩{}和别人的聊天记录，保留人物、事件、约定和未解决的问题，摘要不超过200字。", nick),
+        history: messages.to_vec(),
+        instruction: format!("{}\n只输出摘要正文，不要输出其他内容。", instruction),
+        json_output: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_history, CharTokenizer, RollingSummary, Tokenizer};
+    use crate::testutil::history;
+
+    #[test]
+    fn test_char_tokenizer() {
+        assert_eq!(CharTokenizer.count("你好"), 2);
+        assert_eq!(CharTokenizer.count("hello"), 2);
+        assert_eq!(CharTokenizer.count("好的ok"), 3);
+        assert_eq!(CharTokenizer.count(""), 0);
+    }
+
+    #[test]
+    fn test_split_newest_first() {
+        // 每条消息：发送人2 + 正文2 + 开销4 = 8
+        let history = history(&[("张三", "一一"), ("小明", "二二"), ("张三", "三三")]);
+        assert_eq!(split_history(&history, 100, &CharTokenizer), 0);
+        assert_eq!(split_history(&history, 16, &CharTokenizer), 1);
+        assert_eq!(split_history(&history, 15, &CharTokenizer), 2);
+        assert_eq!(split_history(&history, 0, &CharTokenizer), 2);
+        assert_eq!(split_history(&[], 10, &CharTokenizer), 0);
+    }
+
+    #[test]
+    fn test_rolling_summary() {
+        let overflow = history(&[("张三", "在吗"), ("小明", "在"), ("张三", "周六爬山吗")]);
+        let mut summary = RollingSummary::new();
+        assert_eq!(summary.pending(&overflow), (None, &overflow[..]));
+        summary.update(" 张三约小明周六爬山。 ", &overflow);
+
+        let mut grown = overflow.clone();
+        grown.extend(history(&[("小明", "好啊，几点")]));
+        let (previous, pending) = summary.pending(&grown);
+        assert_eq!(previous, Some("张三约小明周六爬山。"));
+        assert_eq!(pending.len(), 1);
+        assert!(summary.pending(&overflow).1.is_empty());
+
+        let other = history(&[("李四", "在吗"), ("小明", "在"), ("李四", "借我点钱")]);
+        assert_eq!(summary.pending(&other).0, None);
+    }
+}
@@ -0,0 +1,92 @@
+// 对话总结：读取更长的聊天记录，整理出要点、需要我回应的问题和待办事项，方便在群聊中快速跟上进度
+use serde::{Deserialize, Serialize};
+use crate::provider::ChatPrompt;
+use crate::suggest;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Digest {
+    #[serde(default)]
+    pub key_points: Vec<String>,
+    // 别人问我、还没有答复的问题
+    #[serde(default)]
+    pub questions: Vec<String>,
+    #[serde(default)]
+    pub action_items: Vec<String>,
+}
+
+fn clean(items: Vec<String>) -> Vec<String> {
+    items.iter().map(|item| String::from(item.trim())).filter(|item| !item.is_empty()).collect()
+}
+
+impl Digest {
+    pub fn is_empty(&self) -> bool {
+        self.key_points.is_empty() && self.questions.is_empty() && self.action_items.is_empty()
+    }
+
+    // 复制到剪贴板的纯文本，空的部分不输出
+    pub fn to_text(&self) -> String {
+        let sections = [("要点", &self.key_points), ("待我回复", &self.questions), ("待办事项", &self.action_items)];
+        sections.iter().filter(|(_, items)| !items.is_empty()).map(|(title, items)| {
+            let lines: Vec<String> = items.iter().map(|item| format!("- {}", item)).collect();
+            format!("{}：\n{}", title, lines.join("\n"))
+        }).collect::<Vec<String>>().join("\n\n")
+    }
+}
+
+// 聊天记录由调用方按上下文长度截取后填入history
+pub fn digest_prompt(nick: &str, chat: &str) -> ChatPrompt {
+    let chat = if chat.is_empty() { String::new() } else { format!("“{}”中的", chat) };
+    ChatPrompt {
+        nick: String::from(nick),
+        bot_name: String::from("对话总结助手"),
+        system: format!("你负责帮{}梳理{}聊天记录，只依据记录中的内容总结，不要编造。", nick, chat),
+        history: Vec::new(),
+        instruction: format!("请总结以上对话：key_points为讨论的要点，questions为别人向{}提出、{}还没有答复的问题，\
+action_items为需要{}跟进的事项，每项一句话，没有的部分输出空数组。\
+请以JSON格式输出，格式为{{\"key_points\": [], \"questions\": [], \"action_items\": []}}，不要输出JSON之外的任何内容。",
+            nick, nick, nick),
+        json_output: true,
+    }
+}
+
+pub fn parse_digest(content: &str) -> Result<Digest, String> {
+    let body = suggest::strip_fence(content);
+    let object = match (body.find('{'), body.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &body[start..=end],
+        _ => return Err(String::from("总结结果格式有误，请稍后重试")),
+    };
+    let digest: Digest = serde_json::from_str(object).map_err(|_| String::from("总结结果格式有误，请稍后重试"))?;
+    Ok(Digest {
+        key_points: clean(digest.key_points),
+        questions: clean(digest.questions),
+        action_items: clean(digest.action_items),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{digest_prompt, parse_digest, Digest};
+
+    #[test]
+    fn test_prompt() {
+        let prompt = digest_prompt("小明", "项目群");
+        assert_eq!(prompt.system, "你负责帮小明梳理“项目群”中的聊天记录，只依据记录中的内容总结，不要编造。");
+        assert!(prompt.instruction.contains("questions为别人向小明提出、小明还没有答复的问题"));
+        assert!(prompt.json_output);
+        assert!(digest_prompt("小明", "").system.starts_with("你负责帮小明梳理聊天记录"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let content = "```json\n{\"key_points\": [\"周五上线\", \" \"], \"questions\": [\"测试环境谁来部署？\"]}\n```";
+        let digest = parse_digest(content).unwrap();
+        assert_eq!(digest, Digest {
+            key_points: vec![String::from("周五上线")],
+            questions: vec![String::from("测试环境谁来部署？")],
+            action_items: Vec::new(),
+        });
+        assert_eq!(digest.to_text(), "要点：\n- 周五上线\n\n待我回复：\n- 测试环境谁来部署？");
+        assert!(parse_digest("总结如下：无").is_err());
+        assert!(parse_digest("{\"key_points\": []}").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+// 生成编号：回复建议、总结、润色等共用一个编号，每次生成都分配新的编号并中止旧编号的请求，
+// 关闭工具窗口时同样中止。被中止或迟到的结果不是错误，返回None由工具窗口直接忽略
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::task::AbortHandle;
+
+static GENERATION: Mutex<Generation> = Mutex::new(Generation { id: 0, abort: None });
+
+struct Generation {
+    id: u64,
+    abort: Option<AbortHandle>,
+}
+
+pub fn cancel() -> u64 {
+    let mut generation = GENERATION.lock().unwrap();
+    if let Some(handle) = generation.abort.take() {
+        handle.abort();
+    }
+    generation.id += 1;
+    generation.id
+}
+
+pub fn is_current(id: u64) -> bool {
+    GENERATION.lock().is_ok_and(|generation| generation.id == id)
+}
+
+// 在后台执行一次生成并登记中止句柄
+pub async fn run<T: Send + 'static>(id: u64, job: impl Future<Output = Result<T, String>> + Send + 'static) -> Result<Option<T>, String> {
+    let task = tokio::spawn(job);
+    if let Ok(mut current) = GENERATION.lock() {
+        if current.id == id {
+            current.abort = Some(task.abort_handle());
+        } else {
+            task.abort();
+        }
+    }
+    match task.await {
+        Ok(resp) if is_current(id) => resp.map(Some),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use super::{cancel, is_current, run};
+
+    #[test]
+    fn test_cancelled() {
+        let runtime = Runtime::new().unwrap();
+        runtime.block_on(async {
+            let stale = cancel();
+            let pending = tokio::spawn(run(stale, async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Err::<u32, _>(String::from("请求失败"))
+            }));
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let current = cancel();
+            assert!(!is_current(stale) && is_current(current));
+            // 被中止的请求和迟到的失败都不作为错误返回
+            assert_eq!(pending.await.unwrap(), Ok(None));
+            assert_eq!(run(stale, async { Err::<u32, _>(String::from("请求失败")) }).await, Ok(None));
+
+            assert_eq!(run(current, async { Ok(1) }).await, Ok(Some(1)));
+            assert_eq!(run(current, async { Err::<u32, _>(String::from("请求失败")) }).await, Err(String::from("请求失败")));
+        });
+    }
+}
@@ -2,24 +2,70 @@
 // https://tauri.app/v1/guides/features/command
 
 mod auto;
+mod complete;
 mod conf;
+mod context;
+mod digest;
+mod generation;
+mod models;
+mod persona;
+mod polish;
+mod postprocess;
+mod profile;
+mod safety;
+mod prompt;
+mod provider;
+mod refine;
+mod suggest;
+#[cfg(test)]
+mod testutil;
+mod translate;
+mod usage;
 use serde_json;
-use std::path::Path;
+use chrono::Local;
+use std::path::{Path, PathBuf};
 use tokio::runtime::Runtime;
-use std::collections::HashMap;
 use windows::core::{w, PCWSTR};
 use std::sync::{OnceLock, Mutex};
 use window_vibrancy::apply_acrylic;
 use auto::{UiAutoSession, WechatHistory};
 use windows::Win32::System::Threading::CreateMutexW;
 use windows::Win32::Foundation::{GetLastError, HWND, RECT};
-use conf::{ApiRequest, AppConfig, ReplyConstraints, ApiResponse};
+use conf::AppConfig;
+use context::RollingSummary;
+use digest::Digest;
+use models::ModelSpec;
+use serde::Serialize;
+use conf::{ModelConfig, NetworkConfig};
+use provider::{ChainReply, ChatPrompt, ErrorKind, RetryPolicy};
+use suggest::SuggestionSplitter;
+use usage::{UsageReport, UsageStore};
+use prompt::{PromptTemplate, TemplateVars};
+use persona::{Persona, PersonaMemory};
+use profile::{ContactProfile, ProfileStore};
+use refine::RefineThread;
+use safety::{SafetyAction, SafetyPolicy};
+use std::collections::BTreeMap;
+use provider::RequestPreview;
 use windows::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
 use windows::Win32::UI::WindowsAndMessaging::{self, GetForegroundWindow, GetWindowRect, GetWindowThreadProcessId, IsWindowVisible};
 use tauri::{App, AppHandle, CustomMenuItem, GlobalShortcutManager, Manager, PhysicalPosition, PhysicalSize, Position, Size, SystemTray, SystemTrayEvent, SystemTrayMenu, SystemTrayMenuItem, Window, WindowBuilder, WindowEvent};
 
+// 读取聊天记录的条数上限，实际送给模型的条数由上下文长度决定
+const HISTORY_SCAN_LIMIT: usize = 100;
+// 总结对话时读取更多的聊天记录，放不进上下文的早期消息直接舍弃
+const DIGEST_SCAN_LIMIT: usize = 300;
+// 快捷键唤醒工具窗口时的模式，工具窗口据此决定给出回复建议还是总结对话
+const REPLY_MODE: &str = "reply";
+const DIGEST_MODE: &str = "digest";
+
 static CONFIG: OnceLock<Mutex<AppConfig>> = OnceLock::new();
 static SESSION: OnceLock<Mutex<ToolSession>> = OnceLock::new();
+static USAGE: OnceLock<Mutex<UsageStore>> = OnceLock::new();
+static PERSONAS: OnceLock<Mutex<PersonaMemory>> = OnceLock::new();
+static PROFILES: OnceLock<Mutex<ProfileStore>> = OnceLock::new();
+static SUMMARY: Mutex<RollingSummary> = Mutex::new(RollingSummary::new());
+static REFINEMENT: Mutex<Option<RefineThread>> = Mutex::new(None);
 
 unsafe extern "system" fn handle_win_event(_event_hook: HWINEVENTHOOK, event_type: u32, 
 wechat_hwnd: HWND, id_object: i32, id_child: i32, _thread_id: u32, _timestamp: u32) {
@@ -30,6 +76,7 @@ wechat_hwnd: HWND, id_object: i32, id_child: i32, _thread_id: u32, _timestamp: u
         return;
     } else if event_type == WindowsAndMessaging::EVENT_OBJECT_DESTROY || 
     (event_type == WindowsAndMessaging::EVENT_OBJECT_HIDE && !IsWindowVisible(wechat_hwnd).as_bool()) {
+        generation::cancel();
         if let Ok(mut session) = SESSION.get().unwrap().lock() {
             session.detach_hook();    
             let _ = session.window.hide().is_ok();
@@ -67,7 +114,7 @@ impl ToolSession {
         }
     }
 
-    fn attach_wechat(&mut self) -> Result<(), &str> {
+    fn attach_wechat(&mut self, mode: &str) -> Result<(), &str> {
         unsafe {
             if !self.event_hook.is_invalid() {
                 UnhookWinEvent(self.event_hook);
@@ -97,7 +144,7 @@ impl ToolSession {
             let _ = self.window.set_size(Size::Physical(PhysicalSize{
                 width: (wechat_rect.right - wechat_rect.left) as u32, 
                 height: (204.0 * self.sys_dpi) as u32})).is_err();
-            if self.window.emit("show", "").is_err() {
+            if self.window.emit("show", mode).is_err() {
                 Err("激活窗口失败，请稍后重试")
             } else if self.window.show().is_err() || self.window.set_focus().is_err() {
                 Err("展示窗口失败，请稍后重试")
@@ -117,62 +164,271 @@ impl ToolSession {
     }
 }
 
-async fn get_ai_reply(config: AppConfig, chat_hist: &mut Vec<WechatHistory>) -> Result<String, String> {
-    let request_url = match config.model.provider.to_uppercase().as_str() {
-        "MINIMAX" => format!("https://api.minimax.chat/v1/text/chatcompletion_pro?GroupId={}", &config.model.api_group),
-        _ => { return Err(format!("不支持的模型提供商：{}", config.model.provider)); }
+fn config_dir() -> PathBuf {
+    let sys_path = std::env::var_os("LOCALAPPDATA").unwrap();
+    Path::new(sys_path.to_str().unwrap()).join("ChatAssistant")
+}
+
+fn usage_path() -> PathBuf {
+    config_dir().join("usage.json")
+}
+
+// 首次保存配置时写出内置的提示词模板，方便直接修改
+fn write_default_prompt(app_config_root: &Path) {
+    let prompt_path = app_config_root.join("prompt.json");
+    if !prompt_path.exists() {
+        let content = serde_json::to_string_pretty(&PromptTemplate::default()).unwrap();
+        let _ = std::fs::write(prompt_path, content).is_ok();
+    }
+}
+
+fn persona_path() -> PathBuf {
+    config_dir().join("persona.json")
+}
+
+// 未指定人设时沿用该聊天上次使用的人设，指定时记住本次的选择，空字符串表示恢复默认
+fn resolve_persona(config: &AppConfig, chat: &str, requested: Option<String>) -> Result<Option<Persona>, String> {
+    let mut memory = PERSONAS.get_or_init(|| Mutex::new(PersonaMemory::load(&persona_path()))).lock().unwrap();
+    let Some(name) = requested else {
+        // 上次的人设可能已经从配置中删除，此时使用默认
+        return Ok(memory.get(chat).and_then(|name| persona::find(&config.personas, name)).cloned());
     };
-    let model_name = config.model.name.to_lowercase();
-    let max_token = match model_name.as_str() {
-        "abab6-chat" => 2048,
-        "abab5.5-chat" => 2048,
-        _ => { return Err(format!("不支持的模型：{}", config.model.name)); }
+    let persona = match name.trim() {
+        "" => None,
+        name => Some(persona::find(&config.personas, name).ok_or(format!("未找到人设{}，请检查配置", name))?.clone()),
     };
+    memory.remember(chat, &name);
+    if let Err(err) = memory.save(&persona_path()) {
+        println!("保存人设选择失败，err_msg：{}", err);
+    }
+    Ok(persona)
+}
 
-    let command = format!("阅读{}和别人的对话记录，从{}的视角产出5条回复。", config.wechat_nick, config.wechat_nick);
-    let mut bot_settings = HashMap::new();
-    bot_settings.insert("content", command.as_str());
-    bot_settings.insert("bot_name", "智能回复助手");
+fn profile_path() -> PathBuf {
+    config_dir().join("profiles.json")
+}
 
-    chat_hist.push(WechatHistory{
-        sender_type: String::from("USER"),
-        sender_name: config.wechat_nick.clone(),
-        text: format!("以上是我和其他人的对话记录，请结合上述记录，产出5条回复建议。\n{}", 
-        "要求：给出5条不同的回复，有些回复简短一些，有些回复更长。回复不要带序号，不要输出回复建议之外的任何内容，不同的回复之间需要空两行。")
-    });
-    let temperature = config.model.temperature as f32 / 100.0;
-    let reply_constraints = ReplyConstraints::new_minimax("智能回复助手");
-    let req_body = ApiRequest::<WechatHistory> {
-        model: model_name,
-        messages: &chat_hist,
-        temperature: temperature,
-        tokens_to_generate: max_token,
-        bot_setting: vec![&bot_settings],
-        reply_constraints: reply_constraints
-    };                    
-
-    let resp_res = reqwest::Client::new().post(&request_url)
-    .json(&req_body).header("Authorization", format!("Bearer {}", config.model.api_token))
-    .header("Content-Type", "application/json").send().await;
-    if resp_res.is_ok() {
-        let resp = resp_res.unwrap();
-        let resp_json: Result<ApiResponse, _> = resp.json().await;
-        if resp_json.as_ref().is_ok_and(|js: &ApiResponse| {js.base_resp.status_code == 0}) {
-            Ok(resp_json.unwrap().reply)
-        } else if resp_json.is_ok() {
-            let err_msg = resp_json.unwrap().base_resp.status_msg;
-            println!("获取回复失败，err_msg：{}", err_msg);
-            Err(String::from("获取回复失败，请稍后重试"))
+fn profile_store() -> &'static Mutex<ProfileStore> {
+    PROFILES.get_or_init(|| Mutex::new(ProfileStore::load(&profile_path())))
+}
+
+// 当前聊天使用的人设和联系人档案
+struct ChatStyle {
+    chat: String,
+    persona: Option<Persona>,
+    profile: Option<ContactProfile>,
+}
+
+impl ChatStyle {
+    fn resolve(config: &AppConfig, chat: String, persona: Option<String>) -> Result<ChatStyle, String> {
+        let persona = resolve_persona(config, &chat, persona)?;
+        let profile = profile_store().lock().unwrap().get(&chat).cloned();
+        Ok(ChatStyle { chat, persona, profile })
+    }
+
+    fn apply_system(&self, prompt: &mut ChatPrompt) {
+        if let Some(persona) = &self.persona {
+            persona.apply_system(prompt);
+        }
+        if let Some(profile) = &self.profile {
+            profile.apply(&self.chat, prompt);
+        }
+    }
+}
+
+fn usage_store() -> &'static Mutex<UsageStore> {
+    USAGE.get_or_init(|| Mutex::new(UsageStore::load(&usage_path())))
+}
+
+// 每次调用成功后累加用量并立即落盘
+fn record_usage(provider: &str, model: &str, usage: &provider::Usage) {
+    if let Ok(mut store) = usage_store().lock() {
+        store.record(Local::now().date_naive(), provider, model, usage);
+        if let Err(err) = store.save(&usage_path()) {
+            println!("保存用量统计失败，err_msg：{}", err);
+        }
+    }
+}
+
+// 把放不进上下文的早期消息合并进滚动摘要，失败时沿用已有的摘要
+async fn summarize_overflow(config: &AppConfig, overflow: &[WechatHistory]) -> Option<String> {
+    let cached = SUMMARY.lock().unwrap().clone();
+    let (previous, pending) = cached.pending(overflow);
+    if pending.is_empty() {
+        return previous.map(String::from);
+    }
+    let summary_model = config.summary.model.as_ref().unwrap_or(&config.model);
+    let spec = models::validate(summary_model, &config.models).ok()?;
+    let mut model = summary_model.clone();
+    model.max_tokens = Some(spec.max_output_tokens.min(context::SUMMARY_TOKENS as u32));
+    model.stream = false;
+
+    let tokenizer = context::tokenizer();
+    let mut prompt = context::summary_prompt(&config.wechat_nick, previous, &[]);
+    let budget = context::history_budget(tokenizer.as_ref(), &spec, model.max_tokens.unwrap(), &prompt);
+    let split = context::split_history(pending, budget, tokenizer.as_ref());
+    prompt.history = pending[split..].to_vec();
+    match provider::request_reply(&model, &prompt, &RetryPolicy::STANDARD).await {
+        Ok(summary) => {
+            record_usage(&model.provider, &model.name, &summary.usage);
+            let mut cached = SUMMARY.lock().unwrap();
+            cached.update(&summary.content, overflow);
+            Some(cached.text.clone())
+        }
+        Err(err) => {
+            println!("压缩早期聊天记录失败，err_msg：{}", err);
+            previous.map(String::from)
+        }
+    }
+}
+
+// 联系人档案中填写的语言优先，否则按聊天记录判断
+fn reply_language(style: &ChatStyle, chat_hist: &[WechatHistory]) -> String {
+    match style.profile.as_ref().map_or("", |profile| profile.language.trim()) {
+        "" => String::from(prompt::detect_language(chat_hist)),
+        language => String::from(language),
+    }
+}
+
+// 按提示词模板为主模型和备用模型组装请求。主模型放不下的早期消息在开启摘要时压缩，
+// summarize为false时只使用已有的摘要，不额外发起请求
+async fn build_attempts(config: &AppConfig, chat_hist: Vec<WechatHistory>, style: &ChatStyle, summarize: bool) -> Result<Vec<(ModelConfig, ChatPrompt)>, String> {
+    let template = PromptTemplate::load(&config_dir().join("prompt.json"))?;
+    // 联系人档案中填写的称呼和语言优先
+    let profile = style.profile.clone().unwrap_or_default();
+    let language = reply_language(style, &chat_hist);
+    let vars = TemplateVars {
+        nick: config.wechat_nick.clone(),
+        contact: match profile.honorific.trim() {
+            "" => prompt::guess_contact(&config.wechat_nick, &chat_hist),
+            honorific => String::from(honorific),
+        },
+        count: config.suggestion.count,
+        lengths: config.suggestion.length_mix.describe(config.suggestion.count),
+        language,
+    };
+    let spec = models::validate(&config.model, &config.models)?;
+    let model = models::effective_model(&config.model, &config.models)?;
+    let tokenizer = context::tokenizer();
+    let mut skeleton = template.build(&vars, Vec::new(), None);
+    style.apply_system(&mut skeleton);
+    let budget = context::history_budget(tokenizer.as_ref(), &spec, model.max_tokens.unwrap(), &skeleton);
+    let mut split = context::split_history(&chat_hist, budget, tokenizer.as_ref());
+    let mut summary = None;
+    if split > 0 && config.summary.enabled {
+        split = context::split_history(&chat_hist, budget.saturating_sub(context::SUMMARY_TOKENS), tokenizer.as_ref());
+        summary = if summarize {
+            summarize_overflow(config, &chat_hist[..split]).await
         } else {
-            Err(String::from("解析回复内容失败，请稍后重试"))
+            let cached = SUMMARY.lock().unwrap().clone();
+            cached.pending(&chat_hist[..split]).0.map(String::from)
+        };
+    }
+
+    fit_attempts(config, &chat_hist[split..], style.persona.as_ref(), |history| {
+        let mut prompt = template.build(&vars, history, summary.as_deref());
+        style.apply_system(&mut prompt);
+        prompt
+    })
+}
+
+async fn get_ai_reply<F: FnMut(&str)>(config: &AppConfig, chat_hist: Vec<WechatHistory>, style: &ChatStyle, summarize: bool,
+on_delta: F) -> Result<ChainReply, String> {
+    let attempts = build_attempts(config, chat_hist, style, summarize).await?;
+    let reply = provider::request_reply_chain(&attempts, &RetryPolicy::STANDARD, on_delta).await.map_err(|err| {
+        println!("获取回复失败，err_msg：{}", err);
+        err.user_message()
+    })?;
+    record_usage(&reply.provider, &reply.model, &reply.usage);
+    Ok(reply)
+}
+
+// 单条建议，命中安全策略但允许发送时附上原因，翻译模式下附上中文翻译
+#[derive(Serialize, Clone)]
+struct Suggestion {
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warning: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    translation: Option<String>,
+}
+
+impl Suggestion {
+    // 命中需要拦截的规则时返回None
+    fn screened(policy: &SafetyPolicy, text: String) -> Option<Suggestion> {
+        match policy.check(&text) {
+            Some(issue) if issue.action == SafetyAction::Block => None,
+            issue => Some(Suggestion { text, warning: issue.map(|issue| issue.reason), translation: None }),
         }
-    } else {
-        let err_msg = resp_res.unwrap_err().to_string();
-        println!("获取回复失败，err_msg：{}", err_msg);
-        Err(String::from("网络请求失败，请稍后重试"))
     }
 }
 
+// 按配置的条数和长短比例整理建议，模型给出的不够时再请求一次补齐，被安全策略拦截的不计入。
+// 流式输出时每凑齐一条建议就交给on_suggestion，这些建议原样保留在最终结果的最前面
+async fn get_suggestions<F: FnMut(Suggestion)>(config: AppConfig, chat_hist: Vec<WechatHistory>, style: ChatStyle,
+policy: &SafetyPolicy, mut on_suggestion: F) -> Result<(Vec<Suggestion>, ChainReply), String> {
+    let clean = |content: &str| -> Vec<Suggestion> {
+        postprocess::run(&config.postprocess, suggest::parse_suggestions(content)).into_iter()
+            .filter_map(|text| Suggestion::screened(policy, text)).collect()
+    };
+    let text_of = |items: &[Suggestion]| -> Vec<String> { items.iter().map(|item| item.text.clone()).collect() };
+    let mut splitter = SuggestionSplitter::default();
+    let mut shown: Vec<Suggestion> = Vec::new();
+    let reply = get_ai_reply(&config, chat_hist.clone(), &style, true, |delta| {
+        let texts = postprocess::run(&config.postprocess, splitter.push(delta));
+        for suggestion in texts.into_iter().filter_map(|text| Suggestion::screened(policy, text)) {
+            if shown.len() < config.suggestion.count && !shown.iter().any(|item| item.text == suggestion.text) {
+                shown.push(suggestion.clone());
+                on_suggestion(suggestion);
+            }
+        }
+    }).await?;
+    let mut candidates = clean(&reply.content);
+    let selected = suggest::select_suggestions(&text_of(&shown), text_of(&candidates), &config.suggestion);
+    if selected.len() < config.suggestion.count {
+        let mut retry_config = config.clone();
+        retry_config.suggestion.count = config.suggestion.count - selected.len();
+        // 补充请求同样受用量上限约束，用量在get_ai_reply中单独记录；沿用第一次请求生成的摘要，不再重复压缩
+        let within_budget = usage_store().lock().unwrap().check_budget(Local::now().date_naive(), &config.budget);
+        let extra = match within_budget {
+            Ok(_) => get_ai_reply(&retry_config, chat_hist, &style, false, |_| {}).await,
+            Err(err) => Err(err),
+        };
+        match extra {
+            Ok(extra) => candidates.extend(clean(&extra.content)),
+            Err(err) => println!("补充建议失败，err_msg：{}", err),
+        }
+    }
+    let suggestions = suggest::select_suggestions(&text_of(&shown), text_of(&candidates), &config.suggestion).into_iter()
+        .filter_map(|text| shown.iter().chain(candidates.iter()).find(|item| item.text == text).cloned()).collect();
+    Ok((suggestions, reply))
+}
+
+// 把对方最近的消息和各条建议一次性译成中文，返回对方消息的译文。翻译失败时只记录日志，建议照常展示
+async fn translate_reply(config: &AppConfig, language: &str, incoming: &[String], suggestions: &mut [Suggestion]) -> Option<String> {
+    let texts: Vec<String> = incoming.iter().cloned().chain(suggestions.iter().map(|item| item.text.clone())).collect();
+    let translation_model = config.translation.model.as_ref().unwrap_or(&config.model);
+    let mut model = models::effective_model(translation_model, &config.models).ok()?;
+    model.stream = false;
+    let prompt = translate::translation_prompt(&config.wechat_nick, language, &texts);
+    let reply = match provider::request_reply(&model, &prompt, &RetryPolicy::STANDARD).await {
+        Ok(reply) => reply,
+        Err(err) => {
+            println!("翻译建议失败，err_msg：{}", err);
+            return None;
+        }
+    };
+    record_usage(&model.provider, &model.name, &reply.usage);
+    let Some(mut translations) = translate::parse_translations(&reply.content, texts.len()) else {
+        println!("翻译结果与原文条数不一致，err_msg：{}", reply.content);
+        return None;
+    };
+    for (suggestion, translation) in suggestions.iter_mut().zip(translations.split_off(incoming.len())) {
+        suggestion.translation = Some(translation);
+    }
+    if translations.is_empty() { None } else { Some(translations.join("\n")) }
+}
+
 async fn init_tool_wnd(app_handle: &AppHandle) -> Window {
     let window = tauri::WindowBuilder::new(app_handle, "toolWnd", 
     tauri::WindowUrl::App("toolbox.html".into())).visible(false).skip_taskbar(true)
@@ -192,9 +448,47 @@ fn message_toast(app_handle: &AppHandle, message: &str) {
     String::from(message)).unwrap();
 }
 
-fn shortcut_actived(app_handle: &AppHandle) {
+// 快捷键为空时不注册
+fn register_hot_key(app_handle: &AppHandle, key: &str, mode: &'static str) -> tauri::Result<()> {
+    if key.is_empty() {
+        return Ok(());
+    }
+    let handle = app_handle.clone();
+    app_handle.global_shortcut_manager().register(&format!("CommandOrControl+Alt+{}", key), move || {
+        shortcut_actived(&handle, mode);
+    })
+}
+
+fn unregister_hot_key(app_handle: &AppHandle, key: &str) {
+    if !key.is_empty() {
+        let _ = app_handle.global_shortcut_manager().unregister(&format!("CommandOrControl+Alt+{}", key)).is_ok();
+    }
+}
+
+// 快捷键按(唤醒, 对话总结)传入。先注销旧的两个再注册新的，两个快捷键互换时才不会冲突；
+// 任何一个注册失败都撤销已注册的新快捷键，并恢复旧的快捷键
+fn rebind_hot_keys(app_handle: &AppHandle, old_keys: (&str, &str), new_keys: (&str, &str)) -> Result<(), String> {
+    unregister_hot_key(app_handle, old_keys.0);
+    unregister_hot_key(app_handle, old_keys.1);
+    let result = if register_hot_key(app_handle, new_keys.0, REPLY_MODE).is_err() {
+        Err(String::from("注册快捷键失败"))
+    } else if register_hot_key(app_handle, new_keys.1, DIGEST_MODE).is_err() {
+        unregister_hot_key(app_handle, new_keys.0);
+        Err(String::from("注册对话总结的快捷键失败"))
+    } else {
+        Ok(())
+    };
+    if result.is_err() {
+        let _ = register_hot_key(app_handle, old_keys.0, REPLY_MODE).is_ok();
+        let _ = register_hot_key(app_handle, old_keys.1, DIGEST_MODE).is_ok();
+    }
+    result
+}
+
+fn shortcut_actived(app_handle: &AppHandle, mode: &str) {
+    generation::cancel();
     if let Ok(mut wechat) = SESSION.get().unwrap().lock() {
-        if let Err(msg) = wechat.attach_wechat() {
+        if let Err(msg) = wechat.attach_wechat(mode) {
             message_toast(app_handle, msg);
         }
     } else {
@@ -202,60 +496,350 @@ fn shortcut_actived(app_handle: &AppHandle) {
     }    
 }
 
+// 粘贴到微信前再按安全策略检查一次。需要确认时返回原因，用户确认后带上confirmed重新调用
 #[tauri::command]
-async fn submit_wechat(text: String, ctrl_pressed: bool, app_handle: tauri::AppHandle) -> Result<(), ()> {
+async fn submit_wechat(text: String, ctrl_pressed: bool, confirmed: bool, app_handle: tauri::AppHandle) -> Result<Option<String>, ()> {
+    if let Some(reason) = screen_outgoing(&text, confirmed, &app_handle)? {
+        return Ok(Some(reason));
+    }
     let session = UiAutoSession::new();
     if let Err(message) = session.wechat_send(text, ctrl_pressed) {
         message_toast(&app_handle, message.as_str());
         Err(())
     } else {
-        Ok(())
+        Ok(None)
+    }
+}
+
+// 按安全策略检查要填入输入框的完整消息：拦截时提示并返回Err，需要确认且用户还没确认时返回确认的原因
+fn screen_outgoing(text: &str, confirmed: bool, app_handle: &tauri::AppHandle) -> Result<Option<String>, ()> {
+    let safety = CONFIG.get().and_then(|config| config.lock().ok())
+        .map_or(Default::default(), |config| config.safety.clone());
+    match SafetyPolicy::new(&safety).map(|policy| policy.check(text)) {
+        Err(message) => {
+            message_toast(app_handle, message.as_str());
+            Err(())
+        }
+        Ok(Some(issue)) if issue.action == SafetyAction::Block => {
+            message_toast(app_handle, format!("已拦截：{}", issue.reason).as_str());
+            Err(())
+        }
+        Ok(Some(issue)) if !confirmed => Ok(Some(issue.reason)),
+        _ => Ok(None),
     }
 }
 
+// 返回给工具窗口的建议，同时注明是哪个模型、哪个人设给出的。翻译模式下附上对方最近消息的译文
+#[derive(Serialize)]
+struct ReplyContent {
+    suggestions: Vec<Suggestion>,
+    provider: String,
+    model: String,
+    persona: String,
+    // 可供切换的人设，随建议一起返回，工具窗口不必每次唤醒都重新读取配置
+    personas: Vec<String>,
+    language: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    incoming: Option<String>,
+}
+
 #[tauri::command]
-async fn get_reply_content() -> Result<Vec<String>, String> {
-    let app_config: AppConfig;
-    let mut chat_messages: Vec<WechatHistory>;
+async fn get_reply_content(persona: Option<String>, app_handle: tauri::AppHandle) -> Result<Option<ReplyContent>, String> {
+    let id = generation::cancel();
+    let chat_messages: Vec<WechatHistory>;
+    let chat_title: String;
     {
         let uia = auto::UiAutoSession::new();
-        let wechat_resp = uia.wechat_content();
+        chat_title = uia.chat_title().unwrap_or_default();
+        let wechat_resp = uia.wechat_content(HISTORY_SCAN_LIMIT);
         if wechat_resp.is_ok() {
             chat_messages = wechat_resp.unwrap();
         } else {
             return Err(wechat_resp.unwrap_err());
         }
-        if let Ok(config) = CONFIG.get().unwrap().try_lock() {
-            app_config = config.clone();
-        } else {
-            return Err(String::from("请求过于频繁，请稍后重试"));
-        }
     }
+    let app_config = CONFIG.get().ok_or(String::from("请先保存配置"))?
+        .lock().map_err(|_| String::from("读取配置失败，请稍后重试"))?.clone();
+    usage_store().lock().unwrap().check_budget(Local::now().date_naive(), &app_config.budget)?;
+    let style = ChatStyle::resolve(&app_config, chat_title, persona)?;
+    let persona_name = style.persona.as_ref().map_or(String::new(), |persona| persona.name.clone());
+    let personas = app_config.personas.iter().map(|persona| persona.name.clone()).collect();
+    let language = reply_language(&style, &chat_messages);
+    let incoming = translate::latest_incoming(&app_config.wechat_nick, &chat_messages);
     
     if chat_messages.is_empty() {
-        Err(String::from("未找到可供分析的聊天记录，无法产出建议"))
+        return Err(String::from("未找到可供分析的聊天记录，无法产出建议"));
+    }
+    // 每凑齐一条建议就推送给工具窗口，不必等待全部生成完毕，翻译在全部生成后补上
+    let task_language = language.clone();
+    let resp = generation::run(id, async move {
+        let policy = SafetyPolicy::new(&app_config.safety)?;
+        let config = app_config.clone();
+        let (mut suggestions, reply) = get_suggestions(app_config, chat_messages, style, &policy, |suggestion| {
+            if generation::is_current(id) {
+                let _ = app_handle.emit_to("toolWnd", "suggestion", suggestion).is_ok();
+            }
+        }).await?;
+        let mut translated = None;
+        if config.translation.applies(&task_language) && generation::is_current(id) {
+            translated = translate_reply(&config, &task_language, &incoming, &mut suggestions).await;
+        }
+        Ok((suggestions, reply, translated))
+    }).await?;
+    let Some((result, reply, incoming)) = resp else {
+        return Ok(None);
+    };
+    if result.is_empty() {
+        Err(String::from("未产出有价值的建议，请稍后重试"))
     } else {
-        let resp = get_ai_reply(
-            app_config, &mut chat_messages).await;
-        if resp.is_ok() {
-            let message = resp.unwrap();
-            let result: Vec<String> = message.split("\n").into_iter().filter(
-            |line| !line.is_empty()).map(|s| String::from(
-                s.trim().trim_end_matches('。'))).collect();
-            if result.is_empty() {
-                Err(String::from("未产出有价值的建议，请稍后重试"))
-            } else if result.len() == 1 {
-                let result: Vec<String> = message.split(' ').into_iter().filter(
-                    |line| !line.is_empty()).map(|s| String::from(
-                        s.trim().trim_end_matches('。'))).collect();
-                Ok(result)
-            } else {
-                Ok(result)
+        Ok(Some(ReplyContent { suggestions: result, provider: reply.provider, model: reply.model, persona: persona_name,
+            personas, language, incoming }))
+    }
+}
+
+// 为主模型和备用模型组装请求：各个模型的上下文长度不同，按各自的预算从最新的消息往前截取，
+// 由build把截取后的聊天记录填入提示词。指定人设时同时使用人设的采样参数
+fn fit_attempts(config: &AppConfig, history: &[WechatHistory], persona: Option<&Persona>,
+build: impl Fn(Vec<WechatHistory>) -> ChatPrompt) -> Result<Vec<(ModelConfig, ChatPrompt)>, String> {
+    let tokenizer = context::tokenizer();
+    let mut attempts = Vec::new();
+    for (index, candidate) in std::iter::once(&config.model).chain(config.fallbacks.iter()).enumerate() {
+        let attempt = models::validate(candidate, &config.models).and_then(|spec| {
+            let mut model = models::effective_model(candidate, &config.models)?;
+            if let Some(persona) = persona {
+                persona.apply_sampling(&spec, &mut model);
             }
-        } else {
-            Err(resp.unwrap_err())
+            let budget = context::history_budget(tokenizer.as_ref(), &spec, model.max_tokens.unwrap(), &build(Vec::new()));
+            let split = context::split_history(history, budget, tokenizer.as_ref());
+            Ok((model, build(history[split..].to_vec())))
+        });
+        match attempt {
+            Ok(attempt) => attempts.push(attempt),
+            Err(err) if index == 0 => return Err(err),
+            Err(err) => println!("跳过备用模型{}，err_msg：{}", candidate.name, err),
         }
     }
+    Ok(attempts)
+}
+
+// 模型的回复，messages为回答所用的模型实际收到的聊天记录条数
+struct Generated {
+    reply: ChainReply,
+    messages: usize,
+}
+
+// 总结、润色、续写和修改共用的生成流程：检查用量上限后在后台依次尝试主模型和备用模型并记录用量。
+// build组装不含聊天记录的提示词，parse从回复中解析结果；传入聊天名称时人设和联系人档案同样生效
+async fn generate<T: Send + 'static>(id: u64, action: &'static str, chat_messages: Vec<WechatHistory>, styled_chat: Option<String>,
+build: impl FnOnce(&AppConfig) -> ChatPrompt + Send + 'static,
+parse: impl FnOnce(Generated, &AppConfig, &SafetyPolicy) -> Result<T, String> + Send + 'static) -> Result<Option<T>, String> {
+    let app_config = CONFIG.get().ok_or(String::from("请先保存配置"))?
+        .lock().map_err(|_| String::from("读取配置失败，请稍后重试"))?.clone();
+    usage_store().lock().unwrap().check_budget(Local::now().date_naive(), &app_config.budget)?;
+    let style = styled_chat.map(|chat| ChatStyle::resolve(&app_config, chat, None)).transpose()?;
+
+    generation::run(id, async move {
+        let policy = SafetyPolicy::new(&app_config.safety)?;
+        let mut skeleton = build(&app_config);
+        if let Some(style) = &style {
+            style.apply_system(&mut skeleton);
+        }
+        let persona = style.as_ref().and_then(|style| style.persona.as_ref());
+        let attempts = fit_attempts(&app_config, &chat_messages, persona, |history| ChatPrompt { history, ..skeleton.clone() })?;
+        let reply = provider::request_reply_chain(&attempts, &RetryPolicy::STANDARD, |_| {}).await.map_err(|err| {
+            println!("{}失败，err_msg：{}", action, err);
+            err.user_message()
+        })?;
+        record_usage(&reply.provider, &reply.model, &reply.usage);
+        let messages = attempts.iter().find(|(model, _)| model.name == reply.model)
+            .map_or(chat_messages.len(), |(_, prompt)| prompt.history.len());
+        parse(Generated { reply, messages }, &app_config, &policy)
+    }).await
+}
+
+// 返回给工具窗口的对话总结，text用于复制到剪贴板，messages为实际参与总结的消息条数
+#[derive(Serialize)]
+struct ChatDigest {
+    #[serde(flatten)]
+    digest: Digest,
+    text: String,
+    messages: usize,
+    provider: String,
+    model: String,
+}
+
+// 与回复建议共用生成编号，关闭工具窗口或重新生成时中止
+#[tauri::command]
+async fn get_chat_digest() -> Result<Option<ChatDigest>, String> {
+    let id = generation::cancel();
+    let (chat_messages, chat_title) = {
+        let uia = auto::UiAutoSession::new();
+        (uia.wechat_content(DIGEST_SCAN_LIMIT)?, uia.chat_title().unwrap_or_default())
+    };
+    if chat_messages.is_empty() {
+        return Err(String::from("未找到可供总结的聊天记录"));
+    }
+    generate(id, "总结对话", chat_messages, None, move |config| digest::digest_prompt(&config.wechat_nick, &chat_title),
+    |Generated { reply, messages }, _, _| {
+        let digest = digest::parse_digest(&reply.content)?;
+        if digest.is_empty() {
+            return Err(String::from("聊天记录中没有可总结的内容"));
+        }
+        Ok(ChatDigest { text: digest.to_text(), digest, messages, provider: reply.provider, model: reply.model })
+    }).await
+}
+
+// 草稿的改写结果，同样经过安全检查
+#[derive(Serialize)]
+struct DraftRewrite {
+    label: String,
+    #[serde(flatten)]
+    suggestion: Suggestion,
+}
+
+#[derive(Serialize)]
+struct PolishedDraft {
+    draft: String,
+    rewrites: Vec<DraftRewrite>,
+    provider: String,
+    model: String,
+}
+
+// 读取输入框中的草稿并改写，人设和联系人档案同样生效
+#[tauri::command]
+async fn polish_draft() -> Result<Option<PolishedDraft>, String> {
+    let id = generation::cancel();
+    let (draft, chat_messages, chat_title) = {
+        let uia = auto::UiAutoSession::new();
+        let draft = uia.read_draft()?;
+        (draft, uia.wechat_content(HISTORY_SCAN_LIMIT).unwrap_or_default(), uia.chat_title().unwrap_or_default())
+    };
+    if draft.trim().is_empty() {
+        return Err(String::from("输入框中还没有草稿，请先写下想说的话"));
+    }
+    let prompt_draft = draft.clone();
+    generate(id, "润色草稿", chat_messages, Some(chat_title), move |config| polish::polish_prompt(&config.wechat_nick, &prompt_draft),
+    |Generated { reply, .. }, _, policy| {
+        let rewrites: Vec<DraftRewrite> = polish::parse_rewrites(&reply.content)?.into_iter().filter_map(|rewrite| {
+            Suggestion::screened(policy, rewrite.text).map(|suggestion| DraftRewrite { label: rewrite.label, suggestion })
+        }).collect();
+        if rewrites.is_empty() {
+            return Err(String::from("未产出有价值的改写，请稍后重试"));
+        }
+        Ok(PolishedDraft { draft, rewrites, provider: reply.provider, model: reply.model })
+    }).await
+}
+
+// 用选中的改写替换输入框中的草稿，不会直接发送
+#[tauri::command]
+async fn replace_draft(original: String, text: String, confirmed: bool, app_handle: tauri::AppHandle) -> Result<Option<String>, ()> {
+    if let Some(reason) = screen_outgoing(&text, confirmed, &app_handle)? {
+        return Ok(Some(reason));
+    }
+    let session = UiAutoSession::new();
+    if let Err(message) = session.replace_draft(&original, text) {
+        message_toast(&app_handle, message.as_str());
+        Err(())
+    } else {
+        Ok(None)
+    }
+}
+
+#[derive(Serialize)]
+struct DraftCompletion {
+    prefix: String,
+    completions: Vec<Suggestion>,
+    provider: String,
+    model: String,
+}
+
+// 续写输入框中光标之前的内容
+#[tauri::command]
+async fn complete_draft() -> Result<Option<DraftCompletion>, String> {
+    let id = generation::cancel();
+    let (prefix, chat_messages, chat_title) = {
+        let uia = auto::UiAutoSession::new();
+        let prefix = uia.draft_before_caret()?;
+        (prefix, uia.wechat_content(HISTORY_SCAN_LIMIT).unwrap_or_default(), uia.chat_title().unwrap_or_default())
+    };
+    if prefix.trim().is_empty() {
+        return Err(String::from("光标前还没有内容，请先写下消息的开头"));
+    }
+    let prompt_prefix = prefix.clone();
+    generate(id, "续写草稿", chat_messages, Some(chat_title), move |config| complete::completion_prompt(&config.wechat_nick, &prompt_prefix),
+    |Generated { reply, .. }, _, policy| {
+        // 安全检查针对插入后的整条消息
+        let completions: Vec<Suggestion> = complete::parse_completions(&reply.content, &prefix).into_iter().filter_map(|text| {
+            let full = Suggestion::screened(policy, format!("{}{}", prefix, text))?;
+            Some(Suggestion { text, ..full })
+        }).collect();
+        if completions.is_empty() {
+            return Err(String::from("未产出有价值的续写，请稍后重试"));
+        }
+        Ok(DraftCompletion { prefix, completions, provider: reply.provider, model: reply.model })
+    }).await
+}
+
+// 把选中的续写插入到光标处，不会直接发送
+#[tauri::command]
+async fn insert_completion(prefix: String, text: String, confirmed: bool, app_handle: tauri::AppHandle) -> Result<Option<String>, ()> {
+    // 检查插入后的整条消息，只看续写的部分可能漏掉跨越开头的敏感内容
+    if let Some(reason) = screen_outgoing(&format!("{}{}", prefix, text), confirmed, &app_handle)? {
+        return Ok(Some(reason));
+    }
+    let session = UiAutoSession::new();
+    if let Err(message) = session.insert_at_caret(&prefix, text) {
+        message_toast(&app_handle, message.as_str());
+        Err(())
+    } else {
+        Ok(None)
+    }
+}
+
+#[derive(Serialize)]
+struct RefinedReply {
+    variants: Vec<Suggestion>,
+    // 当前是第几轮修改
+    round: usize,
+    provider: String,
+    model: String,
+}
+
+// 按要求修改选中的建议。选中的是上一轮的修改结果时沿用之前的修改记录，否则重新开始
+#[tauri::command]
+async fn refine_reply(text: String, instruction: String) -> Result<Option<RefinedReply>, String> {
+    let id = generation::cancel();
+    let (chat_messages, chat_title) = {
+        let uia = auto::UiAutoSession::new();
+        (uia.wechat_content(HISTORY_SCAN_LIMIT).unwrap_or_default(), uia.chat_title().unwrap_or_default())
+    };
+    let mut thread = REFINEMENT.lock().unwrap().clone().filter(|thread| thread.continues(&chat_title, &text))
+        .unwrap_or_else(|| RefineThread::new(&chat_title));
+    thread.push(&text, &instruction)?;
+
+    let prompt_thread = thread.clone();
+    let Some((result, thread)) = generate(id, "修改建议", chat_messages, Some(chat_title),
+    move |config| prompt_thread.prompt(&config.wechat_nick),
+    move |Generated { reply, .. }, config, policy| {
+        let candidates = refine::parse_variants(&reply.content, &text);
+        let variants: Vec<Suggestion> = postprocess::run(&config.postprocess, candidates).into_iter()
+            .filter_map(|variant| Suggestion::screened(policy, variant)).take(refine::VARIANT_COUNT).collect();
+        if variants.is_empty() {
+            return Err(String::from("未产出有价值的修改，请换个要求试试"));
+        }
+        thread.set_variants(variants.iter().map(|variant| variant.text.clone()).collect());
+        let round = thread.rounds().len();
+        Ok((RefinedReply { variants, round, provider: reply.provider, model: reply.model }, thread))
+    }).await? else {
+        return Ok(None);
+    };
+    *REFINEMENT.lock().unwrap() = Some(thread);
+    Ok(Some(result))
+}
+
+#[tauri::command]
+fn cancel_reply() {
+    generation::cancel();
 }
 
 #[tauri::command]
@@ -289,63 +873,164 @@ fn load_config() -> Result<AppConfig, ()> {
     }
 }
 
+// 用当前的聊天记录和提示词模板渲染出将要发送的请求，不包含API Key
+#[tauri::command]
+async fn preview_prompt() -> Result<RequestPreview, String> {
+    let (chat_messages, chat_title) = {
+        let uia = auto::UiAutoSession::new();
+        (uia.wechat_content(HISTORY_SCAN_LIMIT)?, uia.chat_title().unwrap_or_default())
+    };
+    let app_config = CONFIG.get().ok_or(String::from("请先保存配置"))?
+        .lock().map_err(|_| String::from("读取配置失败，请稍后重试"))?.clone();
+    let style = ChatStyle::resolve(&app_config, chat_title, None)?;
+    let attempts = build_attempts(&app_config, chat_messages, &style, false).await?;
+    let (model, prompt) = &attempts[0];
+    provider::preview_request(model, prompt).map_err(|err| err.user_message())
+}
+
+#[tauri::command]
+async fn current_chat() -> Result<String, String> {
+    let uia = auto::UiAutoSession::new();
+    uia.chat_title()
+}
+
+#[tauri::command]
+fn load_profiles() -> BTreeMap<String, ContactProfile> {
+    profile_store().lock().unwrap().contacts().clone()
+}
+
+#[tauri::command]
+fn save_profile(chat: String, profile: ContactProfile) -> Result<(), String> {
+    let mut store = profile_store().lock().unwrap();
+    store.set(&chat, profile)?;
+    store.save(&profile_path()).map_err(|_| String::from("保存联系人档案失败，请检查权限问题"))
+}
+
+#[tauri::command]
+fn delete_profile(chat: String) -> Result<(), String> {
+    let mut store = profile_store().lock().unwrap();
+    if !store.remove(&chat) {
+        return Err(format!("未找到{}的联系人档案", chat));
+    }
+    store.save(&profile_path()).map_err(|_| String::from("保存联系人档案失败，请检查权限问题"))
+}
+
+#[tauri::command]
+fn load_usage() -> UsageReport {
+    usage_store().lock().unwrap().report(Local::now().date_naive())
+}
+
+// 用界面上尚未保存的网络设置测试能否访问模型接口
+#[tauri::command]
+async fn check_network(network: NetworkConfig, model: ModelConfig) -> Result<String, String> {
+    match provider::check_network(&network, &model).await {
+        Ok(status) => Ok(format!("连接成功，接口返回HTTP {}", status)),
+        Err(err) if err.kind == ErrorKind::Config || err.hint.is_some() => Err(err.user_message()),
+        Err(err) => Err(format!("{}\n{}", err.user_message(), err.detail)),
+    }
+}
+
+// 摘要模型、翻译模型和备用模型的校验规则与主模型相同
+fn check_model(model: &ModelConfig, custom: &[ModelSpec]) -> Result<(), String> {
+    provider::validate_model(model).map_err(|err| err.user_message())?;
+    models::validate(model, custom).map(|_| ())
+}
+
+// 配置写入文件后再替换访问模型接口的客户端，保存失败时继续使用原来的网络设置
+fn apply_network(network: &NetworkConfig) {
+    if let Err(err) = provider::configure_network(network) {
+        println!("网络配置有误，继续使用原来的设置，err_msg：{}", err);
+    }
+}
+
+fn write_config_file(config_json: &str) -> Result<(), String> {
+    let sys_path = std::env::var_os("LOCALAPPDATA").unwrap();
+    let app_data_path = sys_path.to_str().unwrap();
+    let app_config_root = Path::new(app_data_path);
+    let app_config_root = app_config_root.join("ChatAssistant");
+    if !app_config_root.exists() && std::fs::create_dir(&app_config_root).is_err() {
+        Err(String::from("写入配置失败，请检查权限问题"))
+    } else if std::fs::write(app_config_root.join("config.json"), config_json).is_err() {
+        Err(String::from("保存配置失败，请检查权限问题"))
+    } else {
+        write_default_prompt(&app_config_root);
+        Ok(())
+    }
+}
+
 #[tauri::command]
 fn save_config(config: AppConfig, app_handle: tauri::AppHandle) -> Result<(), String> {
     if config.hot_key.len() != 1 || config.wechat_nick.is_empty() {
         Err(String::from("配置不完整"))
+    } else if config.digest_hot_key.len() > 1 || config.digest_hot_key.eq_ignore_ascii_case(&config.hot_key) {
+        Err(String::from("对话总结的快捷键需要是单个字符，且不能与唤醒快捷键相同"))
     } else if config.model.temperature <= 0 || config.model.temperature > 100 {
         Err(String::from("随机度应介于1-100之间"))
     } else if config.model.provider.is_empty() || config.model.name.is_empty() {
         Err(String::from("请填写模型相关配置"))
-    } else if config.model.api_group.is_empty() || config.model.api_token.is_empty() {
-        Err(String::from("请填写模型API相关配置"))
+    } else if let Err(err) = provider::validate_model(&config.model) {
+        Err(err.user_message())
+    } else if let Err(err) = models::validate(&config.model, &config.models) {
+        Err(err)
+    } else if let Some(Err(err)) = config.summary.model.as_ref().map(|model| check_model(model, &config.models)) {
+        Err(format!("摘要模型配置有误：{}", err))
+    } else if let Some(Err(err)) = config.translation.model.as_ref().map(|model| check_model(model, &config.models)) {
+        Err(format!("翻译模型配置有误：{}", err))
+    } else if let Some((index, err)) = config.fallbacks.iter().enumerate()
+        .find_map(|(index, model)| check_model(model, &config.models).err().map(|err| (index, err))) {
+        Err(format!("第{}个备用模型配置有误：{}", index + 1, err))
+    } else if let Err(err) = persona::validate(&config.personas) {
+        Err(err)
+    } else if let Err(err) = config.suggestion.validate() {
+        Err(err)
+    } else if let Err(err) = postprocess::validate(&config.postprocess) {
+        Err(err)
+    } else if let Err(err) = SafetyPolicy::new(&config.safety) {
+        Err(err)
+    } else if let Err(err) = provider::build_client(&config.network) {
+        Err(err.user_message())
     } else if CONFIG.get().is_none() {
-        let config_json = serde_json::to_string(&config);
-        let acce = format!("CommandOrControl+Alt+{}", config.hot_key);
-        CONFIG.get_or_init(|| { Mutex::new(config) });
-        if app_handle.global_shortcut_manager().register(acce.as_str(), move || {
-            shortcut_actived(&app_handle);
-        }).is_err() {
-            return Err(String::from("注册快捷键失败"));
-        }
-        let sys_path = std::env::var_os("LOCALAPPDATA").unwrap();
-        let app_data_path = sys_path.to_str().unwrap();
-        let app_config_root = Path::new(app_data_path);
-        let app_config_root = app_config_root.join("ChatAssistant");
-        if !app_config_root.exists() && std::fs::create_dir(&app_config_root).is_err() {
-            Err(String::from("写入配置失败，请检查权限问题"))
-        } else if std::fs::write(app_config_root.join("config.json"), config_json.unwrap()).is_err() {
-            Err(String::from("保存配置失败，请检查权限问题"))
-        } else {
-            Ok(())
+        // 快捷键注册成功、配置写入文件后才初始化CONFIG，失败时可以直接重新保存
+        let new_keys = (config.hot_key.as_str(), config.digest_hot_key.as_str());
+        rebind_hot_keys(&app_handle, ("", ""), new_keys)?;
+        if let Err(err) = write_config_file(&serde_json::to_string(&config).unwrap()) {
+            let _ = rebind_hot_keys(&app_handle, new_keys, ("", ""));
+            return Err(err);
         }
+        apply_network(&config.network);
+        CONFIG.get_or_init(|| { Mutex::new(config) });
+        Ok(())
     } else if let Ok(mut old_config) = CONFIG.get().unwrap().try_lock() {
+        // 快捷键注册成功、配置写入文件后才更新内存中的配置，失败时保持原来的配置和快捷键
         let old_hot_key = old_config.hot_key.clone();
-        let config_json = serde_json::to_string(&config);
-        old_config.model = config.model;
-        old_config.hot_key = config.hot_key.clone();
-        old_config.wechat_nick = config.wechat_nick;
-        if old_hot_key != config.hot_key {
-            let old_acce = format!("CommandOrControl+Alt+{}", old_hot_key);
-            let new_acce = format!("CommandOrControl+Alt+{}", config.hot_key);
-            let _ = app_handle.global_shortcut_manager().unregister(&old_acce).is_ok();
-            if app_handle.global_shortcut_manager().register(new_acce.as_str(), move || {
-                shortcut_actived(&app_handle);
-            }).is_err() {
-                return Err(String::from("注册快捷键失败"));
-            }
+        let old_digest_key = old_config.digest_hot_key.clone();
+        let old_keys = (old_hot_key.as_str(), old_digest_key.as_str());
+        let new_keys = (config.hot_key.as_str(), config.digest_hot_key.as_str());
+        if old_keys != new_keys {
+            rebind_hot_keys(&app_handle, old_keys, new_keys)?;
         }
-        let sys_path = std::env::var_os("LOCALAPPDATA").unwrap();
-        let app_data_path = sys_path.to_str().unwrap();
-        let app_config_root = Path::new(app_data_path);
-        let app_config_root = app_config_root.join("ChatAssistant");
-        if !app_config_root.exists() && std::fs::create_dir(&app_config_root).is_err() {
-            Err(String::from("写入配置失败，请检查权限问题"))
-        } else if std::fs::write(app_config_root.join("config.json"), config_json.unwrap()).is_err() {
-            Err(String::from("保存配置失败，请检查权限问题"))
-        } else {
-            Ok(())
+        if let Err(err) = write_config_file(&serde_json::to_string(&config).unwrap()) {
+            if old_keys != new_keys {
+                let _ = rebind_hot_keys(&app_handle, new_keys, old_keys);
+            }
+            return Err(err);
         }
+        apply_network(&config.network);
+        old_config.model = config.model;
+        old_config.models = config.models;
+        old_config.summary = config.summary;
+        old_config.fallbacks = config.fallbacks;
+        old_config.network = config.network;
+        old_config.budget = config.budget;
+        old_config.personas = config.personas;
+        old_config.suggestion = config.suggestion;
+        old_config.postprocess = config.postprocess;
+        old_config.safety = config.safety;
+        old_config.translation = config.translation;
+        old_config.hot_key = config.hot_key;
+        old_config.digest_hot_key = config.digest_hot_key;
+        old_config.wechat_nick = config.wechat_nick;
+        Ok(())
     } else {
         Err(String::from("保存配置失败，系统繁忙，请稍后重试"))
     }
@@ -361,6 +1046,9 @@ fn main() {
         }
     }
     let config = load_config();
+    if let Err(err) = config.as_ref().map_or(Ok(()), |config| provider::configure_network(&config.network)) {
+        println!("网络配置有误，使用默认设置，err_msg：{}", err);
+    }
     let menu_config = CustomMenuItem::new("config".to_string(), "打开设置");
     let menu_about = CustomMenuItem::new("about".to_string(), "关于瓜皮助手");
     let menu_exit = CustomMenuItem::new("exit".to_string(), "退出");
@@ -372,11 +1060,15 @@ fn main() {
     .system_tray(system_tray).setup(move|app: &mut App| {
         let handle = app.handle();
         SESSION.get_or_init(|| Mutex::new(ToolSession::new(&handle)));
-        if config.is_ok() {
-            let accelerator = format!("CommandOrControl+Alt+{}", config.unwrap().hot_key);
+        if let Ok(config) = config {
+            if register_hot_key(&handle, &config.digest_hot_key, DIGEST_MODE).is_err() {
+                tauri::api::dialog::message(None::<&tauri::Window>, "快捷键冲突",
+                "注册对话总结的快捷键失败，请检查快捷键是否冲突，或者进入设置修改快捷键。");
+            }
+            let accelerator = format!("CommandOrControl+Alt+{}", config.hot_key);
             if app.global_shortcut_manager().is_registered(accelerator.as_str())
             .is_ok_and(|res| !res) && app.global_shortcut_manager().register(accelerator.as_str(), 
-            move|| { shortcut_actived(&handle) }).is_err() {
+            move|| { shortcut_actived(&handle, REPLY_MODE) }).is_err() {
                 tauri::api::dialog::message(None::<&tauri::Window>, "快捷键冲突", 
                 "注册快捷键失败，将无法唤醒瓜皮助手。请检查快捷键是否冲突，然后重启程序，或者进入设置修改快捷键。");
             }    
@@ -415,7 +1107,9 @@ fn main() {
             }
         }    
         _ => ()
-    }).invoke_handler(tauri::generate_handler![get_reply_content, submit_wechat, load_config, save_config, reset_and_exit])
+    }).invoke_handler(tauri::generate_handler![get_reply_content, refine_reply, get_chat_digest, polish_draft,
+        replace_draft, complete_draft, insert_completion, cancel_reply, check_network, load_usage, preview_prompt,
+        current_chat, load_profiles, save_profile, delete_profile, submit_wechat, load_config, save_config, reset_and_exit])
     .build(tauri::generate_context!()).expect("启动APP失败，请重试！");
 
     app.run(|_app_handle, event| match event {
@@ -0,0 +1,175 @@
+// 模型注册表：记录每个模型的上下文长度、最大输出长度和支持的采样参数。
+// 内置常用模型，也可以在配置文件的models中追加或覆盖，name为"*"时匹配该厂商的所有模型
+use serde::{Deserialize, Serialize};
+use crate::conf::ModelConfig;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingParam {
+    TopP,
+    PresencePenalty,
+    FrequencyPenalty,
+    Stop,
+}
+
+impl SamplingParam {
+    fn label(&self) -> &'static str {
+        match self {
+            SamplingParam::TopP => "top_p",
+            SamplingParam::PresencePenalty => "presence_penalty",
+            SamplingParam::FrequencyPenalty => "frequency_penalty",
+            SamplingParam::Stop => "stop",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelSpec {
+    pub provider: String,
+    pub name: String,
+    pub context_length: u32,
+    pub max_output_tokens: u32,
+    #[serde(default)]
+    pub parameters: Vec<SamplingParam>,
+}
+
+const ALL_PARAMS: &[SamplingParam] = &[SamplingParam::TopP, SamplingParam::PresencePenalty,
+    SamplingParam::FrequencyPenalty, SamplingParam::Stop];
+const TOP_P_STOP: &[SamplingParam] = &[SamplingParam::TopP, SamplingParam::Stop];
+
+const BUILTIN_MODELS: &[(&str, &str, u32, u32, &[SamplingParam])] = &[
+    ("MINIMAX", "abab6-chat", 32768, 2048, &[SamplingParam::TopP]),
+    ("MINIMAX", "abab5.5-chat", 16384, 2048, &[SamplingParam::TopP]),
+    ("OPENAI", "gpt-4o", 128000, 16384, ALL_PARAMS),
+    ("OPENAI", "gpt-4o-mini", 128000, 16384, ALL_PARAMS),
+    ("OPENAI", "gpt-3.5-turbo", 16385, 4096, ALL_PARAMS),
+    ("OPENAI", "deepseek-chat", 65536, 8192, ALL_PARAMS),
+    ("OPENAI", "*", 8192, 2048, ALL_PARAMS),
+    ("LLAMACPP", "*", 4096, 1024, ALL_PARAMS),
+    ("OLLAMA", "*", 4096, 1024, ALL_PARAMS),
+    ("ANTHROPIC", "claude-3-5-sonnet-latest", 200000, 8192, TOP_P_STOP),
+    ("ANTHROPIC", "claude-3-5-haiku-latest", 200000, 8192, TOP_P_STOP),
+    ("ANTHROPIC", "*", 200000, 4096, TOP_P_STOP),
+    ("GLM", "glm-4", 128000, 4095, TOP_P_STOP),
+    ("GLM", "glm-4-flash", 128000, 4095, TOP_P_STOP),
+    ("GLM", "*", 8192, 1024, TOP_P_STOP),
+];
+
+pub fn builtin_models() -> Vec<ModelSpec> {
+    BUILTIN_MODELS.iter().map(|(provider, name, context_length, max_output_tokens, parameters)| ModelSpec {
+        provider: String::from(*provider),
+        name: String::from(*name),
+        context_length: *context_length,
+        max_output_tokens: *max_output_tokens,
+        parameters: parameters.to_vec(),
+    }).collect()
+}
+
+// 查找顺序：自定义的同名模型、内置的同名模型、自定义的通配、内置的通配
+pub fn find_spec(custom: &[ModelSpec], provider: &str, name: &str) -> Option<ModelSpec> {
+    let builtin = builtin_models();
+    let matches = |spec: &&ModelSpec, wanted: &str| spec.provider.eq_ignore_ascii_case(provider.trim())
+        && spec.name.eq_ignore_ascii_case(wanted);
+    custom.iter().find(|spec| matches(spec, name.trim()))
+        .or_else(|| builtin.iter().find(|spec| matches(spec, name.trim())))
+        .or_else(|| custom.iter().find(|spec| matches(spec, "*")))
+        .or_else(|| builtin.iter().find(|spec| matches(spec, "*")))
+        .cloned()
+}
+
+fn used_params(model: &ModelConfig) -> Vec<SamplingParam> {
+    let mut params = Vec::new();
+    if model.top_p.is_some() { params.push(SamplingParam::TopP); }
+    if model.presence_penalty.is_some() { params.push(SamplingParam::PresencePenalty); }
+    if model.frequency_penalty.is_some() { params.push(SamplingParam::FrequencyPenalty); }
+    if !model.stop.is_empty() { params.push(SamplingParam::Stop); }
+    params
+}
+
+// top_p为0时部分厂商直接报错，人设中的top_p使用同样的范围
+pub fn valid_top_p(top_p: f32) -> bool {
+    top_p > 0.0 && top_p <= 1.0
+}
+
+pub fn validate(model: &ModelConfig, custom: &[ModelSpec]) -> Result<ModelSpec, String> {
+    let spec = find_spec(custom, &model.provider, &model.name)
+        .ok_or_else(|| format!("不支持的模型：{}", model.name))?;
+    if model.max_tokens.is_some_and(|tokens| tokens == 0 || tokens > spec.max_output_tokens) {
+        return Err(format!("最大输出长度应介于1-{}之间", spec.max_output_tokens));
+    } else if model.top_p.is_some_and(|top_p| !valid_top_p(top_p)) {
+        return Err(String::from("top_p应介于0-1之间"));
+    } else if [model.presence_penalty, model.frequency_penalty].iter().flatten().any(|p| !(-2.0..=2.0).contains(p)) {
+        return Err(String::from("惩罚系数应介于-2到2之间"));
+    } else if model.stop.len() > 4 {
+        return Err(String::from("停止词最多设置4个"));
+    }
+    if let Some(param) = used_params(model).into_iter().find(|param| !spec.parameters.contains(param)) {
+        return Err(format!("模型{}不支持参数{}", model.name, param.label()));
+    }
+    Ok(spec)
+}
+
+// 校验通过后补全最大输出长度，供各个Provider直接使用
+pub fn effective_model(model: &ModelConfig, custom: &[ModelSpec]) -> Result<ModelConfig, String> {
+    let spec = validate(model, custom)?;
+    let mut effective = model.clone();
+    effective.max_tokens = Some(model.max_tokens.unwrap_or(spec.max_output_tokens));
+    Ok(effective)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{effective_model, find_spec, validate, ModelSpec, SamplingParam};
+    use crate::conf::ModelConfig;
+
+    fn model(provider: &str, name: &str) -> ModelConfig {
+        ModelConfig {
+            name: String::from(name),
+            provider: String::from(provider),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lookup_order() {
+        assert_eq!(find_spec(&[], "minimax", "ABAB6-chat").unwrap().context_length, 32768);
+        assert!(find_spec(&[], "minimax", "abab7-chat").is_none());
+        assert_eq!(find_spec(&[], "ollama", "qwen2:7b").unwrap().name, "*");
+
+        let custom = vec![ModelSpec {
+            provider: String::from("ollama"),
+            name: String::from("qwen2:7b"),
+            context_length: 32768,
+            max_output_tokens: 4096,
+            parameters: vec![SamplingParam::TopP],
+        }];
+        assert_eq!(find_spec(&custom, "OLLAMA", "qwen2:7b").unwrap().context_length, 32768);
+        assert_eq!(find_spec(&custom, "OLLAMA", "llama3").unwrap().context_length, 4096);
+    }
+
+    #[test]
+    fn test_validate_overrides() {
+        let mut config = model("anthropic", "claude-3-5-haiku-latest");
+        config.max_tokens = Some(9000);
+        assert!(validate(&config, &[]).is_err());
+        config.max_tokens = Some(1024);
+        config.top_p = Some(0.9);
+        config.stop = vec![String::from("\n\n")];
+        assert!(validate(&config, &[]).is_ok());
+        config.presence_penalty = Some(0.5);
+        assert_eq!(validate(&config, &[]).unwrap_err(), "模型claude-3-5-haiku-latest不支持参数presence_penalty");
+
+        let mut config = model("openai", "gpt-4o-mini");
+        config.frequency_penalty = Some(3.0);
+        assert!(validate(&config, &[]).is_err());
+    }
+
+    #[test]
+    fn test_effective_max_tokens() {
+        let config = effective_model(&model("minimax", "abab6-chat"), &[]).unwrap();
+        assert_eq!(config.max_tokens, Some(2048));
+        let mut config = model("glm", "glm-4");
+        config.max_tokens = Some(512);
+        assert_eq!(effective_model(&config, &[]).unwrap().max_tokens, Some(512));
+    }
+}
@@ -0,0 +1,178 @@
+// 回复人设：每个人设追加一段系统提示词，并可覆盖随机度等采样参数。
+// 内置正式、轻松、幽默、简洁四种，可以在配置文件的personas中修改或追加
+use std::path::Path;
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::conf::ModelConfig;
+use crate::models::{self, ModelSpec, SamplingParam};
+use crate::provider::ChatPrompt;
+
+const BUILTIN_PERSONAS: &[(&str, &str, i32)] = &[
+    ("正式", "回复的语气正式、礼貌，措辞得体，适合工作和商务场合，不使用网络用语和表情。", 30),
+    ("轻松", "回复的语气轻松自然，像朋友之间聊天，可以使用口语和适量的语气词。", 70),
+    ("幽默", "回复的语气幽默风趣，可以适当调侃，但不能冒犯对方。", 90),
+    ("简洁", "回复尽量简短，直接表达意思，每条回复不超过15个字。", 40),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Persona {
+    pub name: String,
+    // 追加到系统提示词末尾的说明
+    #[serde(default)]
+    pub system: String,
+    // 以下为可选的采样参数，未设置时沿用模型配置
+    #[serde(default)]
+    pub temperature: Option<i32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+}
+
+pub fn default_personas() -> Vec<Persona> {
+    BUILTIN_PERSONAS.iter().map(|(name, system, temperature)| Persona {
+        name: String::from(*name),
+        system: String::from(*system),
+        temperature: Some(*temperature),
+        top_p: None,
+    }).collect()
+}
+
+pub fn find<'a>(personas: &'a [Persona], name: &str) -> Option<&'a Persona> {
+    personas.iter().find(|persona| persona.name == name.trim())
+}
+
+pub fn validate(personas: &[Persona]) -> Result<(), String> {
+    for (index, persona) in personas.iter().enumerate() {
+        if persona.name.trim().is_empty() {
+            return Err(String::from("人设名称不能为空"));
+        }
+        if personas[..index].iter().any(|other| other.name.trim() == persona.name.trim()) {
+            return Err(format!("人设{}重复", persona.name));
+        }
+        if persona.temperature.is_some_and(|temperature| !(1..=100).contains(&temperature)) {
+            return Err(format!("人设{}的随机度需要介于1-100之间", persona.name));
+        }
+        if persona.top_p.is_some_and(|top_p| !models::valid_top_p(top_p)) {
+            return Err(format!("人设{}的Top P需要介于0-1之间", persona.name));
+        }
+    }
+    Ok(())
+}
+
+impl Persona {
+    pub fn apply_system(&self, prompt: &mut ChatPrompt) {
+        if !self.system.trim().is_empty() {
+            prompt.system = format!("{}\n{}", prompt.system, self.system.trim());
+        }
+    }
+
+    // 模型不支持的采样参数直接忽略，避免切换人设后请求失败
+    pub fn apply_sampling(&self, spec: &ModelSpec, model: &mut ModelConfig) {
+        if let Some(temperature) = self.temperature {
+            model.temperature = temperature;
+        }
+        if self.top_p.is_some() && spec.parameters.contains(&SamplingParam::TopP) {
+            model.top_p = self.top_p;
+        }
+    }
+}
+
+// 聊天标题 -> 最后使用的人设，保存在配置目录下的persona.json中
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PersonaMemory {
+    #[serde(default)]
+    chats: BTreeMap<String, String>,
+}
+
+impl PersonaMemory {
+    pub fn load(path: &Path) -> PersonaMemory {
+        std::fs::read_to_string(path).ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string(self).map_err(|err| err.to_string())?;
+        std::fs::write(path, content).map_err(|err| err.to_string())
+    }
+
+    pub fn get(&self, chat: &str) -> Option<&str> {
+        self.chats.get(chat).map(String::as_str)
+    }
+
+    // 人设为空表示恢复默认，不再记录
+    pub fn remember(&mut self, chat: &str, persona: &str) {
+        if chat.is_empty() {
+            return;
+        }
+        if persona.trim().is_empty() {
+            self.chats.remove(chat);
+        } else {
+            self.chats.insert(String::from(chat), String::from(persona.trim()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_personas, find, validate, Persona, PersonaMemory};
+    use crate::conf::ModelConfig;
+    use crate::models::{find_spec, ModelSpec};
+    use crate::provider::stub::sample_prompt;
+    use crate::testutil::temp_path;
+
+    #[test]
+    fn test_apply() {
+        let personas = default_personas();
+        let formal = find(&personas, "正式").unwrap();
+        let mut model = ModelConfig { temperature: 80, ..Default::default() };
+        let mut prompt = sample_prompt();
+        let system = prompt.system.clone();
+        formal.apply_system(&mut prompt);
+        formal.apply_sampling(&find_spec(&[], "openai", "gpt-4o").unwrap(), &mut model);
+        assert_eq!(model.temperature, 30);
+        assert_eq!(prompt.system, format!("{}\n{}", system, formal.system));
+
+        let persona = Persona { name: String::from("自定义"), system: String::new(), temperature: None, top_p: Some(0.5) };
+        let mut model = ModelConfig { temperature: 80, ..Default::default() };
+        persona.apply_system(&mut prompt);
+        persona.apply_sampling(&find_spec(&[], "openai", "gpt-4o").unwrap(), &mut model);
+        assert_eq!((model.temperature, model.top_p), (80, Some(0.5)));
+        assert_eq!(prompt.system, format!("{}\n{}", system, formal.system));
+        // 不支持top_p的模型忽略该参数
+        let spec = ModelSpec { provider: String::from("OPENAI"), name: String::from("o1-mini"),
+            context_length: 128000, max_output_tokens: 4096, parameters: Vec::new() };
+        let mut model = ModelConfig::default();
+        persona.apply_sampling(&spec, &mut model);
+        assert_eq!(model.top_p, None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&default_personas()).is_ok());
+        let mut personas = default_personas();
+        personas.push(Persona { name: String::from(" 幽默"), system: String::new(), temperature: None, top_p: None });
+        assert!(validate(&personas).unwrap_err().contains("重复"));
+        personas.pop();
+        personas[0].temperature = Some(120);
+        assert!(validate(&personas).is_err());
+        personas[0].temperature = None;
+        personas[0].top_p = Some(0.0);
+        assert!(validate(&personas).unwrap_err().contains("Top P"));
+        assert!(find(&personas, "严肃").is_none());
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut memory = PersonaMemory::default();
+        memory.remember("张三", "幽默");
+        memory.remember("工作群", "正式");
+        memory.remember("", "简洁");
+        memory.remember("工作群", "");
+        assert_eq!(memory.get("张三"), Some("幽默"));
+        assert_eq!(memory.get("工作群"), None);
+
+        let path = temp_path("persona.json");
+        memory.save(&path).unwrap();
+        assert_eq!(PersonaMemory::load(&path).chats, memory.chats);
+    }
+}
@@ -0,0 +1,107 @@
+// 润色草稿：读取输入框中自己写好的草稿，结合聊天记录按几种要求分别改写，选中后原地替换草稿
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::provider::ChatPrompt;
+use crate::suggest;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolishStyle {
+    Polite,
+    Shorter,
+    FixTypos,
+}
+
+impl PolishStyle {
+    pub const ALL: [PolishStyle; 3] = [PolishStyle::Polite, PolishStyle::Shorter, PolishStyle::FixTypos];
+
+    fn key(&self) -> &'static str {
+        match self {
+            PolishStyle::Polite => "polite",
+            PolishStyle::Shorter => "shorter",
+            PolishStyle::FixTypos => "fix_typos",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PolishStyle::Polite => "更礼貌",
+            PolishStyle::Shorter => "更简短",
+            PolishStyle::FixTypos => "改错字",
+        }
+    }
+
+    fn requirement(&self) -> &'static str {
+        match self {
+            PolishStyle::Polite => "语气更礼貌、得体的版本",
+            PolishStyle::Shorter => "意思不变、尽量简短的版本",
+            PolishStyle::FixTypos => "只修正错别字、语病和标点，其余保持原样的版本",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Rewrite {
+    pub style: PolishStyle,
+    pub label: String,
+    pub text: String,
+}
+
+// 最近的聊天记录由调用方按上下文长度截取后填入history
+pub fn polish_prompt(nick: &str, draft: &str) -> ChatPrompt {
+    let requirements: Vec<String> = PolishStyle::ALL.iter()
+        .map(|style| format!("{}为{}", style.key(), style.requirement())).collect();
+    let format: Vec<String> = PolishStyle::ALL.iter().map(|style| format!("\"{}\": \"\"", style.key())).collect();
+    ChatPrompt {
+        nick: String::from(nick),
+        bot_name: String::from("草稿润色助手"),
+        system: format!("你负责帮{}修改准备发出的微信消息草稿，保持原意和草稿使用的语言，不要添加草稿中没有的信息。", nick),
+        history: Vec::new(),
+        instruction: format!("我准备发送的草稿是：\n{}\n请结合上述聊天记录分别改写草稿：{}。\
+请以JSON格式输出，格式为{{{}}}，不要输出JSON之外的任何内容。", draft.trim(), requirements.join("，"), format.join(", ")),
+        json_output: true,
+    }
+}
+
+// 按固定的顺序返回改写结果，缺少或为空的改写直接跳过
+pub fn parse_rewrites(content: &str) -> Result<Vec<Rewrite>, String> {
+    let body = suggest::strip_fence(content);
+    let rewrites: HashMap<String, String> = match (body.find('{'), body.rfind('}')) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str(&body[start..=end]).ok(),
+        _ => None,
+    }.ok_or(String::from("润色结果格式有误，请稍后重试"))?;
+    Ok(PolishStyle::ALL.iter().filter_map(|style| {
+        let text = rewrites.get(style.key())?.trim();
+        if text.is_empty() {
+            None
+        } else {
+            Some(Rewrite { style: *style, label: String::from(style.label()), text: String::from(text) })
+        }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_rewrites, polish_prompt, PolishStyle};
+
+    #[test]
+    fn test_prompt() {
+        let prompt = polish_prompt("小明", " 明天我晚点到，你们先开始把 \n");
+        assert!(prompt.instruction.starts_with("我准备发送的草稿是：\n明天我晚点到，你们先开始把\n"));
+        assert!(prompt.instruction.contains("polite为语气更礼貌、得体的版本，shorter为"));
+        assert!(prompt.instruction.contains(r#"{"polite": "", "shorter": "", "fix_typos": ""}"#));
+        assert!(prompt.json_output);
+    }
+
+    #[test]
+    fn test_parse() {
+        let content = "```json\n{\"fix_typos\": \"明天我晚点到，你们先开始吧\", \"polite\": \"不好意思，明天我会晚一点到，大家先开始吧\", \"shorter\": \" \"}\n```";
+        let rewrites = parse_rewrites(content).unwrap();
+        assert_eq!(rewrites.iter().map(|rewrite| rewrite.style).collect::<Vec<_>>(),
+            vec![PolishStyle::Polite, PolishStyle::FixTypos]);
+        assert_eq!(rewrites[1].label, "改错字");
+        assert_eq!(rewrites[1].text, "明天我晚点到，你们先开始吧");
+        assert!(parse_rewrites("明天我晚点到").is_err());
+        assert!(parse_rewrites("{\"polite\": 1}").is_err());
+    }
+}
@@ -0,0 +1,285 @@
+// 建议后处理：按配置文件中postprocess的顺序依次执行，每个处理器可以改写或丢弃建议
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_SIMILARITY: f32 = 0.8;
+const BULLETS: &[char] = &['-', '*', '•', '·', '+'];
+const NUMBER_SEPARATORS: &[char] = &['.', '、', ')', '）', ':', '：'];
+const QUOTES: &[(char, char)] = &[('"', '"'), ('\'', '\''), ('“', '”'), ('‘', '’'), ('「', '」'), ('『', '』')];
+// 模型常给建议加上的标签，如“回复1：”
+const LABELS: &[&str] = &["回复", "建议", "选项"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmojiPolicy {
+    Keep,
+    // 去掉表情，只剩表情的建议被丢弃
+    Strip,
+    // 丢弃带表情的建议
+    Reject,
+}
+
+fn default_similarity() -> f32 {
+    DEFAULT_SIMILARITY
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Processor {
+    // 去掉序号、项目符号和包裹整条建议的引号
+    StripPrefix,
+    // 去掉加粗、行内代码、标题、引用和链接等markdown标记
+    StripMarkdown,
+    // 按字数过滤，max为空时不限制
+    Length {
+        #[serde(default)]
+        min: usize,
+        #[serde(default)]
+        max: Option<usize>,
+    },
+    Emoji { policy: EmojiPolicy },
+    // 去掉标点后的编辑距离相似度达到threshold时视为重复，保留先出现的一条
+    Dedupe {
+        #[serde(default = "default_similarity")]
+        threshold: f32,
+    },
+}
+
+pub fn default_pipeline() -> Vec<Processor> {
+    vec![
+        Processor::StripPrefix,
+        Processor::StripMarkdown,
+        Processor::Length { min: 1, max: None },
+        Processor::Dedupe { threshold: DEFAULT_SIMILARITY },
+    ]
+}
+
+pub fn validate(pipeline: &[Processor]) -> Result<(), String> {
+    for processor in pipeline {
+        match processor {
+            Processor::Length { min, max: Some(max) } if min > max => {
+                return Err(String::from("后处理的最小长度不能大于最大长度"));
+            }
+            Processor::Dedupe { threshold } if !(*threshold > 0.0 && *threshold <= 1.0) => {
+                return Err(String::from("去重的相似度阈值需要介于0-1之间"));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn run(pipeline: &[Processor], suggestions: Vec<String>) -> Vec<String> {
+    pipeline.iter().fold(suggestions, |items, processor| processor.apply(items))
+}
+
+impl Processor {
+    fn apply(&self, items: Vec<String>) -> Vec<String> {
+        match self {
+            Processor::Dedupe { threshold } => dedupe(items, *threshold),
+            _ => items.into_iter().filter_map(|item| self.apply_one(item)).collect(),
+        }
+    }
+
+    fn apply_one(&self, item: String) -> Option<String> {
+        let result = match self {
+            Processor::StripPrefix => strip_prefix(&item),
+            Processor::StripMarkdown => strip_markdown(&item),
+            Processor::Length { min, max } => {
+                let len = item.chars().count();
+                if len < *min || max.is_some_and(|max| len > max) {
+                    return None;
+                }
+                item
+            }
+            Processor::Emoji { policy: EmojiPolicy::Keep } => item,
+            Processor::Emoji { policy: EmojiPolicy::Strip } => strip_emoji(&item),
+            Processor::Emoji { policy: EmojiPolicy::Reject } => {
+                if strip_emoji(&item) != item.trim() {
+                    return None;
+                }
+                item
+            }
+            Processor::Dedupe { .. } => item,
+        };
+        if result.trim().is_empty() { None } else { Some(String::from(result.trim())) }
+    }
+}
+
+// 数字加分隔符，如“1.”、“2、”、“3）”，分隔符后紧跟数字的小数和时间（如3.5、3:30）不算
+fn strip_number(text: &str) -> Option<&str> {
+    let digits = text.find(|c: char| !c.is_ascii_digit())?;
+    let rest = &text[digits..];
+    let separator = rest.chars().next()?;
+    let after = &rest[separator.len_utf8()..];
+    if digits == 0 || !NUMBER_SEPARATORS.contains(&separator) || after.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(after)
+}
+
+// 括号包裹的序号，如“(1)”、“（2）”
+fn strip_paren_number(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix('(').or(text.strip_prefix('（'))?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    let rest = &rest[digits..];
+    if digits == 0 { None } else { rest.strip_prefix(')').or(rest.strip_prefix('）')) }
+}
+
+fn strip_label(text: &str) -> Option<&str> {
+    let rest = LABELS.iter().find_map(|label| text.strip_prefix(label))?;
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+    rest.strip_prefix('：').or(rest.strip_prefix(':'))
+}
+
+fn strip_prefix(text: &str) -> String {
+    let mut text = text.trim();
+    loop {
+        let stripped = strip_number(text).or_else(|| strip_paren_number(text)).or_else(|| strip_label(text))
+            .or_else(|| text.strip_prefix(BULLETS).filter(|rest| rest.starts_with(' ')));
+        match stripped {
+            Some(rest) => text = rest.trim_start(),
+            None => break,
+        }
+    }
+    for (open, close) in QUOTES {
+        if let Some(inner) = text.strip_prefix(*open).and_then(|rest| rest.strip_suffix(*close)) {
+            if !inner.is_empty() && !inner.contains([*open, *close]) {
+                return String::from(inner.trim());
+            }
+        }
+    }
+    String::from(text)
+}
+
+fn strip_markdown(text: &str) -> String {
+    let lines: Vec<String> = text.lines().map(|line| {
+        let line = line.trim_start();
+        let line = line.trim_start_matches('#').trim_start();
+        let line = line.strip_prefix('>').unwrap_or(line).trim_start();
+        strip_links(&line.replace("**", "").replace("__", "").replace('`', ""))
+    }).collect();
+    lines.join("\n")
+}
+
+// [文字](链接) 只保留文字
+fn strip_links(text: &str) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        let Some(middle) = rest[start..].find("](").map(|pos| start + pos) else { break; };
+        let Some(end) = rest[middle..].find(')').map(|pos| middle + pos) else { break; };
+        output.push_str(&rest[..start]);
+        output.push_str(&rest[start + 1..middle]);
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c, '\u{1F000}'..='\u{1FAFF}' | '\u{2600}'..='\u{27BF}' | '\u{2B00}'..='\u{2BFF}'
+        | '\u{FE0F}' | '\u{200D}' | '\u{20E3}')
+}
+
+// 同时去掉微信的文字表情，如[微笑]、[捂脸]
+fn strip_emoji(text: &str) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        let Some(end) = rest[start..].find(']').map(|pos| start + pos) else { break; };
+        let name = &rest[start + 1..end];
+        output.push_str(&rest[..start]);
+        if name.is_empty() || name.chars().count() > 4 || !name.chars().all(|c| c > '\u{2E7F}') {
+            output.push_str(&rest[start..=end]);
+        }
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    let output: String = output.chars().filter(|c| !is_emoji(*c)).collect();
+    String::from(output.trim())
+}
+
+fn normalize(text: &str) -> Vec<char> {
+    let chars: Vec<char> = text.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect();
+    if chars.is_empty() { text.chars().collect() } else { chars }
+}
+
+fn similarity(a: &[char], b: &[char]) -> f32 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    1.0 - previous[b.len()] as f32 / a.len().max(b.len()) as f32
+}
+
+fn dedupe(items: Vec<String>, threshold: f32) -> Vec<String> {
+    let mut kept: Vec<(String, Vec<char>)> = Vec::new();
+    for item in items {
+        let normalized = normalize(&item);
+        if !kept.iter().any(|(_, other)| similarity(&normalized, other) >= threshold) {
+            kept.push((item, normalized));
+        }
+    }
+    kept.into_iter().map(|(item, _)| item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_pipeline, run, validate, EmojiPolicy, Processor};
+
+    fn process(processor: Processor, items: &[&str]) -> Vec<String> {
+        run(&[processor], items.iter().map(|item| String::from(*item)).collect())
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let items = ["1. 好的", "2、明天见", "3）行", "(4) 没问题", "- 收到", "回复5：“我马上到”",
+            "\"OK\"", "3.5元不贵", "10点见", "“他说”和“我说”", "3:30见", "1：30可以", "2: 3点见"];
+        assert_eq!(process(Processor::StripPrefix, &items),
+            vec!["好的", "明天见", "行", "没问题", "收到", "我马上到", "OK", "3.5元不贵", "10点见", "“他说”和“我说”",
+                "3:30见", "1：30可以", "3点见"]);
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        assert_eq!(process(Processor::StripMarkdown, &["**好的**，`明天`见", "## 标题", "> 引用", "看[这里](https://example.com)"]),
+            vec!["好的，明天见", "标题", "引用", "看这里"]);
+    }
+
+    #[test]
+    fn test_length_and_emoji() {
+        let items = ["好", "好的呀😄", "[捂脸]", "收到[OK]👍🏻"];
+        assert_eq!(process(Processor::Length { min: 2, max: Some(4) }, &items), vec!["好的呀😄", "[捂脸]"]);
+        assert_eq!(process(Processor::Emoji { policy: EmojiPolicy::Strip }, &items), vec!["好", "好的呀", "收到[OK]"]);
+        assert_eq!(process(Processor::Emoji { policy: EmojiPolicy::Reject }, &items), vec!["好"]);
+        assert_eq!(process(Processor::Emoji { policy: EmojiPolicy::Keep }, &items).len(), 4);
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let items = ["好的，明天见！", "好的明天见", "好的，明天见吧", "明天几点？", "OK", "ok."];
+        assert_eq!(process(Processor::Dedupe { threshold: 0.8 }, &items), vec!["好的，明天见！", "明天几点？", "OK"]);
+        assert_eq!(process(Processor::Dedupe { threshold: 1.0 }, &items).len(), 4);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let items = vec![String::from("1. **好的**"), String::from("2. 好的"), String::from("3. "), String::from("4. 明天见")];
+        assert_eq!(run(&default_pipeline(), items), vec!["好的", "明天见"]);
+        assert!(validate(&default_pipeline()).is_ok());
+        assert!(validate(&[Processor::Length { min: 5, max: Some(2) }]).is_err());
+        assert!(validate(&[Processor::Dedupe { threshold: 0.0 }]).is_err());
+
+        let pipeline: Vec<Processor> = serde_json::from_str(
+            r#"[{"type": "strip_prefix"}, {"type": "emoji", "policy": "strip"}, {"type": "dedupe"}]"#).unwrap();
+        assert_eq!(pipeline[2], Processor::Dedupe { threshold: 0.8 });
+    }
+}
@@ -0,0 +1,154 @@
+// 联系人档案：按聊天标题（联系人备注或群名）记录关系、回复语言、称呼和禁忌话题，
+// 保存在配置目录下的profiles.json中，生成回复时追加到系统提示词
+use std::path::Path;
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::provider::ChatPrompt;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ContactProfile {
+    // 与对方的关系，如上司、客户、好友
+    #[serde(default)]
+    pub relationship: String,
+    // 回复使用的语言，为空时按聊天记录判断
+    #[serde(default)]
+    pub language: String,
+    // 对对方的称呼，如王总、张老师
+    #[serde(default)]
+    pub honorific: String,
+    #[serde(default)]
+    pub forbidden_topics: Vec<String>,
+    // 其他需要模型了解的情况
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl ContactProfile {
+    pub fn is_empty(&self) -> bool {
+        self.relationship.trim().is_empty() && self.language.trim().is_empty() && self.honorific.trim().is_empty()
+            && self.forbidden_topics.iter().all(|topic| topic.trim().is_empty()) && self.notes.trim().is_empty()
+    }
+
+    // 转换为追加到系统提示词的说明，语言通过提示词模板的{language}生效，这里不再重复
+    pub fn describe(&self, chat: &str) -> Option<String> {
+        let mut lines = Vec::new();
+        if !self.relationship.trim().is_empty() {
+            lines.push(format!("当前聊天是{}，对方是我的{}。", chat, self.relationship.trim()));
+        }
+        if !self.honorific.trim().is_empty() {
+            lines.push(format!("称呼对方时使用“{}”。", self.honorific.trim()));
+        }
+        let topics: Vec<&str> = self.forbidden_topics.iter().map(|topic| topic.trim())
+            .filter(|topic| !topic.is_empty()).collect();
+        if !topics.is_empty() {
+            lines.push(format!("回复中不要涉及以下话题：{}。", topics.join("、")));
+        }
+        if !self.notes.trim().is_empty() {
+            lines.push(format!("补充说明：{}", self.notes.trim()));
+        }
+        if lines.is_empty() { None } else { Some(lines.join("\n")) }
+    }
+
+    pub fn apply(&self, chat: &str, prompt: &mut ChatPrompt) {
+        if let Some(description) = self.describe(chat) {
+            prompt.system = format!("{}\n{}", prompt.system, description);
+        }
+    }
+}
+
+// 聊天标题 -> 联系人档案
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ProfileStore {
+    #[serde(default)]
+    contacts: BTreeMap<String, ContactProfile>,
+}
+
+impl ProfileStore {
+    pub fn load(path: &Path) -> ProfileStore {
+        std::fs::read_to_string(path).ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        std::fs::write(path, content).map_err(|err| err.to_string())
+    }
+
+    pub fn contacts(&self) -> &BTreeMap<String, ContactProfile> {
+        &self.contacts
+    }
+
+    pub fn get(&self, chat: &str) -> Option<&ContactProfile> {
+        self.contacts.get(chat.trim())
+    }
+
+    // 档案内容为空时等同于删除
+    pub fn set(&mut self, chat: &str, mut profile: ContactProfile) -> Result<(), String> {
+        if chat.trim().is_empty() {
+            return Err(String::from("请填写联系人或群聊名称"));
+        }
+        profile.forbidden_topics.retain(|topic| !topic.trim().is_empty());
+        if profile.is_empty() {
+            self.contacts.remove(chat.trim());
+        } else {
+            self.contacts.insert(String::from(chat.trim()), profile);
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, chat: &str) -> bool {
+        self.contacts.remove(chat.trim()).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContactProfile, ProfileStore};
+    use crate::provider::stub::sample_prompt;
+    use crate::testutil::temp_path;
+
+    fn boss() -> ContactProfile {
+        ContactProfile {
+            relationship: String::from("上司"),
+            language: String::from("中文"),
+            honorific: String::from("王总"),
+            forbidden_topics: vec![String::from("薪资"), String::from(" ")],
+            notes: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(boss().describe("王伟").unwrap(),
+            "当前聊天是王伟，对方是我的上司。\n称呼对方时使用“王总”。\n回复中不要涉及以下话题：薪资。");
+        let profile = ContactProfile { language: String::from("英文"), ..Default::default() };
+        assert_eq!(profile.describe("Tom"), None);
+        assert!(!profile.is_empty());
+
+        let mut prompt = sample_prompt();
+        let system = prompt.system.clone();
+        profile.apply("Tom", &mut prompt);
+        assert_eq!(prompt.system, system);
+        boss().apply("王伟", &mut prompt);
+        assert!(prompt.system.starts_with(&system) && prompt.system.ends_with("薪资。"));
+    }
+
+    #[test]
+    fn test_store() {
+        let mut store = ProfileStore::default();
+        store.set(" 王伟 ", boss()).unwrap();
+        assert_eq!(store.get("王伟").unwrap().forbidden_topics, vec![String::from("薪资")]);
+        assert!(store.set("", boss()).is_err());
+        store.set("李四", ContactProfile { notes: String::from("大学室友"), ..Default::default() }).unwrap();
+        store.set("李四", ContactProfile { forbidden_topics: vec![String::new()], ..Default::default() }).unwrap();
+        assert!(store.get("李四").is_none());
+        assert!(!store.remove("李四"));
+
+        let path = temp_path("profiles.json");
+        store.save(&path).unwrap();
+        assert_eq!(ProfileStore::load(&path).contacts(), store.contacts());
+        assert!(store.remove("王伟"));
+        assert!(store.contacts().is_empty());
+    }
+}
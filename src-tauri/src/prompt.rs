@@ -0,0 +1,239 @@
+// 提示词模板：保存在配置目录下的prompt.json中，修改后下次生成即生效。
+// 模板中的{nick}、{contact}、{count}、{lengths}、{history}、{language}会被替换，其余的花括号原样保留
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::auto::WechatHistory;
+use crate::provider::ChatPrompt;
+
+const VARIABLES: &[&str] = &["nick", "contact", "count", "lengths", "history", "language"];
+const BOT_NAME: &str = "智能回复助手";
+
+const DEFAULT_SYSTEM: &str = "阅读{nick}和别人的对话记录，从{nick}的视角产出{count}条回复。";
+const DEFAULT_INSTRUCTION: &str = "以上是我和其他人的对话记录，请结合上述记录，产出{count}条回复建议。\n\
+要求：给出{count}条不同的回复，其中{lengths}。回复不要带序号，使用{language}回复。\
+请以JSON格式输出，格式为{\"suggestions\": [\"回复1\", \"回复2\"]}，不要输出JSON之外的任何内容。";
+
+fn default_system() -> String {
+    String::from(DEFAULT_SYSTEM)
+}
+
+fn default_instruction() -> String {
+    String::from(DEFAULT_INSTRUCTION)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    #[serde(default = "default_system")]
+    pub system: String,
+    #[serde(default = "default_instruction")]
+    pub instruction: String,
+}
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        PromptTemplate { system: default_system(), instruction: default_instruction() }
+    }
+}
+
+// 渲染模板需要的变量，聊天记录单独传入
+#[derive(Debug, Clone)]
+pub struct TemplateVars {
+    pub nick: String,
+    pub contact: String,
+    pub count: usize,
+    // 各长度回复的条数说明，如“2条简短，3条适中”
+    pub lengths: String,
+    pub language: String,
+}
+
+// 找出形如{name}的占位符，返回起止位置和变量名
+fn placeholders(template: &str) -> Vec<(usize, usize, &str)> {
+    let mut result = Vec::new();
+    let mut search = 0;
+    while let Some(offset) = template[search..].find('{') {
+        let start = search + offset;
+        let name_len = template[start + 1..].find(|c: char| !(c.is_ascii_lowercase() || c == '_'))
+            .unwrap_or(template.len() - start - 1);
+        let end = start + 1 + name_len;
+        if name_len > 0 && template[end..].starts_with('}') {
+            result.push((start, end + 1, &template[start + 1..end]));
+            search = end + 1;
+        } else {
+            search = start + 1;
+        }
+    }
+    result
+}
+
+fn render(template: &str, value: impl Fn(&str) -> String) -> String {
+    let mut output = String::new();
+    let mut last = 0;
+    for (start, end, name) in placeholders(template) {
+        output.push_str(&template[last..start]);
+        output.push_str(&value(name));
+        last = end;
+    }
+    output.push_str(&template[last..]);
+    output
+}
+
+fn format_history(history: &[WechatHistory]) -> String {
+    history.iter().map(|item| format!("{}：{}", item.sender_name, item.text)).collect::<Vec<String>>().join("\n")
+}
+
+impl PromptTemplate {
+    // 读取配置目录下的模板，文件不存在时使用内置模板
+    pub fn load(path: &Path) -> Result<PromptTemplate, String> {
+        let Ok(content) = std::fs::read_to_string(path) else { return Ok(PromptTemplate::default()); };
+        let template: PromptTemplate = serde_json::from_str(&content)
+            .map_err(|err| format!("提示词模板不是有效的JSON：{}", err))?;
+        template.validate()?;
+        Ok(template)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.system.trim().is_empty() || self.instruction.trim().is_empty() {
+            return Err(String::from("提示词模板的system和instruction都不能为空"));
+        }
+        let unknown = [&self.system, &self.instruction].iter().flat_map(|template| placeholders(template))
+            .find(|(_, _, name)| !VARIABLES.contains(name));
+        match unknown {
+            Some((_, _, name)) => Err(format!("提示词模板中有未知变量{{{}}}，可用的变量有：{}", name,
+                VARIABLES.iter().map(|name| format!("{{{}}}", name)).collect::<Vec<String>>().join("、"))),
+            None => Ok(()),
+        }
+    }
+
+    fn uses_history(&self) -> bool {
+        [&self.system, &self.instruction].iter()
+            .any(|template| placeholders(template).iter().any(|(_, _, name)| *name == "history"))
+    }
+
+    // 模板引用了{history}时聊天记录以文本形式嵌入模板，否则按消息逐条发送
+    pub fn build(&self, vars: &TemplateVars, history: Vec<WechatHistory>, summary: Option<&str>) -> ChatPrompt {
+        let inline = self.uses_history();
+        let history_text = format_history(&history);
+        let value = |name: &str| match name {
+            "nick" => vars.nick.clone(),
+            "contact" => vars.contact.clone(),
+            "count" => vars.count.to_string(),
+            "lengths" => vars.lengths.clone(),
+            "history" => history_text.clone(),
+            "language" => vars.language.clone(),
+            _ => String::new(),
+        };
+        let mut system = render(&self.system, value);
+        if let Some(summary) = summary {
+            system = format!("{}\n更早的对话摘要：{}", system, summary);
+        }
+        ChatPrompt {
+            nick: vars.nick.clone(),
+            bot_name: String::from(BOT_NAME),
+            system,
+            history: if inline { Vec::new() } else { history },
+            instruction: render(&self.instruction, value),
+            json_output: true,
+        }
+    }
+}
+
+// 最近一条不是自己发出的消息的发送人
+pub fn guess_contact(nick: &str, history: &[WechatHistory]) -> String {
+    history.iter().rev().find(|item| item.sender_name != nick && !item.sender_name.is_empty())
+        .map_or(String::from("对方"), |item| item.sender_name.clone())
+}
+
+// 按文字的书写系统粗略判断对话使用的语言
+pub fn detect_language(history: &[WechatHistory]) -> &'static str {
+    let (mut han, mut kana, mut hangul, mut latin) = (0, 0, 0, 0);
+    for c in history.iter().flat_map(|item| item.text.chars()) {
+        match c {
+            '\u{4E00}'..='\u{9FFF}' => han += 1,
+            '\u{3040}'..='\u{30FF}' => kana += 1,
+            '\u{AC00}'..='\u{D7AF}' => hangul += 1,
+            c if c.is_ascii_alphabetic() => latin += 1,
+            _ => {}
+        }
+    }
+    // 日文夹杂汉字，假名占到一定比例即视为日文；拉丁字母按4个折算成1个汉字比较
+    if kana > 0 && kana * 3 >= han {
+        "日文"
+    } else if hangul > han && hangul * 4 > latin {
+        "韩文"
+    } else if latin > han * 4 {
+        "英文"
+    } else {
+        "中文"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_language, guess_contact, PromptTemplate, TemplateVars};
+    use crate::testutil::{history, temp_path};
+
+    fn vars() -> TemplateVars {
+        TemplateVars {
+            nick: String::from("小明"),
+            contact: String::from("张三"),
+            count: 3,
+            lengths: String::from("1条简短，2条较长"),
+            language: String::from("中文"),
+        }
+    }
+
+    #[test]
+    fn test_default_template() {
+        let history = history(&[("张三", "明天一起吃饭吗？")]);
+        let prompt = PromptTemplate::default().build(&vars(), history.clone(), None);
+        assert_eq!(prompt.system, "阅读小明和别人的对话记录，从小明的视角产出3条回复。");
+        assert!(prompt.instruction.contains("产出3条回复建议"));
+        assert!(prompt.instruction.contains("其中1条简短，2条较长。"));
+        assert!(prompt.instruction.contains("使用中文回复"));
+        assert!(prompt.instruction.contains(r#"{"suggestions": ["回复1", "回复2"]}"#));
+        assert_eq!(prompt.history, history);
+    }
+
+    #[test]
+    fn test_inline_history() {
+        let template = PromptTemplate {
+            system: String::from("你是{nick}的助手{unknown"),
+            instruction: String::from("{contact}说：\n{history}\n请给出{count}条回复，{ 保持原样 }"),
+        };
+        assert!(template.validate().is_ok());
+        let history = history(&[("张三", "在吗"), ("小明", "在")]);
+        let prompt = template.build(&vars(), history, Some("约了周六吃饭"));
+        assert_eq!(prompt.system, "你是小明的助手{unknown\n更早的对话摘要：约了周六吃饭");
+        assert_eq!(prompt.instruction, "张三说：\n张三：在吗\n小明：在\n请给出3条回复，{ 保持原样 }");
+        assert!(prompt.history.is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let template = PromptTemplate { system: String::from("你好{name}"), ..Default::default() };
+        assert!(template.validate().unwrap_err().contains("{name}"));
+        let template = PromptTemplate { instruction: String::from("  "), ..Default::default() };
+        assert!(template.validate().is_err());
+
+        let path = temp_path("prompt.json");
+        std::fs::write(&path, r#"{"system": "帮{nick}回复"}"#).unwrap();
+        let template = PromptTemplate::load(&path).unwrap();
+        assert_eq!(template.system, "帮{nick}回复");
+        assert_eq!(template.instruction, PromptTemplate::default().instruction);
+        std::fs::write(&path, r#"{"system": "帮{nik}回复"}"#).unwrap();
+        assert!(PromptTemplate::load(&path).is_err());
+        assert_eq!(PromptTemplate::load(&path.with_extension("missing")).unwrap(), PromptTemplate::default());
+    }
+
+    #[test]
+    fn test_contact_and_language() {
+        let english = history(&[("张三", "See you tomorrow"), ("小明", "OK")]);
+        assert_eq!(guess_contact("小明", &english), "张三");
+        assert_eq!(guess_contact("小明", &[]), "对方");
+        assert_eq!(detect_language(&english), "英文");
+        assert_eq!(detect_language(&history(&[("张三", "明天见，Good night")])), "中文");
+        assert_eq!(detect_language(&history(&[("田中", "明日また会いましょう")])), "日文");
+        assert_eq!(detect_language(&history(&[("김", "내일 봐요")])), "韩文");
+        assert_eq!(detect_language(&[]), "中文");
+    }
+}
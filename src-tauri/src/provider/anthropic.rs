@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use reqwest::{Client, RequestBuilder};
+use crate::conf::ModelConfig;
+use super::{ChatPrompt, ErrorKind, ProviderError, ReplyProvider, StreamEvent, Usage};
+
+const DEFAULT_BASE: &str = "https://api.anthropic.com";
+const API_VERSION: &str = "2023-06-01";
+const DEFAULT_MAX_TOKENS: u32 = 2048;
+const TYPED_FIELDS: &[&str] = &["model", "system", "max_tokens", "temperature", "top_p", "stop_sequences", "stream", "messages"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContentBlock {
+    #[serde(rename = "type")]
+    pub block_type: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+impl ContentBlock {
+    fn text(text: String) -> Self {
+        ContentBlock { block_type: String::from("text"), text }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Message {
+    pub role: &'static str,
+    pub content: Vec<ContentBlock>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MessagesRequest<'a> {
+    pub model: &'a str,
+    pub system: &'a str,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub stop_sequences: &'a [String],
+    pub stream: bool,
+    pub messages: Vec<Message>,
+    // 模型参数中填写的其他字段，如top_k
+    #[serde(flatten)]
+    pub options: &'a HashMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApiError {
+    #[serde(rename = "type", default)]
+    pub error_type: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn into_error(self, status: u16) -> ProviderError {
+        let kind = match self.error_type.as_str() {
+            "authentication_error" | "permission_error" => ErrorKind::Auth,
+            "rate_limit_error" => ErrorKind::RateLimit,
+            "overloaded_error" | "api_error" => ErrorKind::Server,
+            _ if self.message.contains("credit balance") => ErrorKind::Quota,
+            _ => ErrorKind::from_status(status),
+        };
+        ProviderError::new(kind, format!("{}：{}", self.error_type, self.message))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MessagesResponse {
+    #[serde(default)]
+    pub content: Vec<ContentBlock>,
+    #[serde(default)]
+    pub error: Option<ApiError>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UsageMessage {
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+// 非流式响应和message_delta的用量在顶层，message_start的用量在message中
+#[derive(Deserialize, Debug)]
+pub struct UsageChunk {
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    #[serde(default)]
+    pub message: Option<UsageMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamDelta {
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamChunk {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(default)]
+    pub delta: Option<StreamDelta>,
+    #[serde(default)]
+    pub error: Option<ApiError>,
+}
+
+// 接口要求user和assistant严格交替且以user开头，同一方连续发出的消息合并为多个内容块
+pub fn merge_turns(prompt: &ChatPrompt) -> Vec<Message> {
+    let mut messages: Vec<Message> = Vec::new();
+    let turns = prompt.history.iter().map(|item| {
+        if item.sender_name == prompt.nick {
+            ("assistant", item.text.clone())
+        } else {
+            ("user", format!("{}：{}", item.sender_name, item.text))
+        }
+    }).chain(std::iter::once(("user", prompt.instruction.clone())));
+
+    for (role, text) in turns {
+        if messages.is_empty() && role == "assistant" {
+            messages.push(Message { role: "user", content: vec![ContentBlock::text(String::from("（对话开始）"))] });
+        }
+        match messages.last_mut() {
+            Some(last) if last.role == role => last.content.push(ContentBlock::text(text)),
+            _ => messages.push(Message { role, content: vec![ContentBlock::text(text)] }),
+        }
+    }
+    messages
+}
+
+pub struct AnthropicProvider;
+
+impl ReplyProvider for AnthropicProvider {
+    fn default_base(&self) -> &str {
+        DEFAULT_BASE
+    }
+
+    fn validate(&self, model: &ModelConfig) -> Result<(), ProviderError> {
+        super::check_options(model, TYPED_FIELDS)?;
+        if model.api_token.is_empty() {
+            Err(ProviderError::new(ErrorKind::Config, "请填写API Key"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn build_request(&self, client: &Client, model: &ModelConfig, prompt: &ChatPrompt, stream: bool) -> Result<RequestBuilder, ProviderError> {
+        self.validate(model)?;
+        let req_body = MessagesRequest {
+            model: model.name.trim(),
+            system: &prompt.system,
+            max_tokens: model.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            temperature: model.temperature as f32 / 100.0,
+            top_p: model.top_p,
+            stop_sequences: &model.stop,
+            stream,
+            messages: merge_turns(prompt),
+            options: &model.options,
+        };
+        Ok(client.post(super::endpoint(model, DEFAULT_BASE, "/v1/messages")).json(&req_body)
+        .header("x-api-key", &model.api_token)
+        .header("anthropic-version", API_VERSION))
+    }
+
+    fn parse_response(&self, status: u16, body: &str) -> Result<String, ProviderError> {
+        let resp: MessagesResponse = match serde_json::from_str(body) {
+            Ok(resp) => resp,
+            Err(_) if !(200..300).contains(&status) => {
+                return Err(ProviderError::from_status(status, format!("HTTP {}：{}", status, body)));
+            }
+            Err(err) => { return Err(ProviderError::new(ErrorKind::Parse, err.to_string())); }
+        };
+        if let Some(error) = resp.error {
+            return Err(error.into_error(status));
+        }
+        let text: Vec<String> = resp.content.into_iter()
+            .filter(|block| block.block_type == "text").map(|block| block.text).collect();
+        if text.is_empty() {
+            Err(ProviderError::new(ErrorKind::Parse, "content为空"))
+        } else {
+            Ok(text.join(""))
+        }
+    }
+
+    // 只关心content_block_delta中的文本增量，message_stop表示结束
+    fn parse_stream_line(&self, line: &str) -> Result<StreamEvent, ProviderError> {
+        let Some(data) = super::sse_data(line) else { return Ok(StreamEvent::Ignore); };
+        let Ok(chunk) = serde_json::from_str::<StreamChunk>(data) else { return Ok(StreamEvent::Ignore); };
+        match (chunk.event_type.as_str(), chunk.error, chunk.delta) {
+            (_, Some(error), _) => Err(error.into_error(200)),
+            ("message_stop", _, _) => Ok(StreamEvent::Done),
+            ("content_block_delta", _, Some(delta)) if !delta.text.is_empty() => Ok(StreamEvent::Delta(delta.text)),
+            _ => Ok(StreamEvent::Ignore),
+        }
+    }
+
+    fn parse_usage(&self, body: &str) -> Option<Usage> {
+        let chunk: UsageChunk = serde_json::from_str(super::sse_data(body).unwrap_or(body)).ok()?;
+        chunk.usage.or(chunk.message.and_then(|message| message.usage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_turns, AnthropicProvider};
+    use crate::provider::stub::{sample_prompt, StubServer, FAST_RETRY};
+    use crate::provider::{request_reply, ErrorKind, ReplyProvider, StreamEvent, Usage};
+    use crate::testutil::{history, test_model};
+
+    #[test]
+    fn test_merge_turns() {
+        let mut prompt = sample_prompt();
+        prompt.history = history(&[("小明", "在吗"), ("张三", "在"), ("李四", "我也在"), ("小明", "晚上聚餐"),
+            ("小明", "七点"), ("张三", "好的")]);
+        let messages = merge_turns(&prompt);
+        let roles: Vec<&str> = messages.iter().map(|message| message.role).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user", "assistant", "user"]);
+        assert_eq!(messages[2].content.len(), 2);
+        assert_eq!(messages[3].content.len(), 2);
+        // 最后一条对方消息与指令合并
+        assert_eq!(messages[4].content.len(), 2);
+        assert_eq!(messages[4].content[1].text, prompt.instruction);
+    }
+
+    #[test]
+    fn test_messages_api() {
+        let server = StubServer::start(vec![(200, r#"{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"text","text":"好的"}],"stop_reason":"end_turn","usage":{"input_tokens":120,"output_tokens":8}}"#)]);
+        let mut config = test_model("anthropic", server.url());
+        config.options.insert(String::from("top_k"), serde_json::Value::from(40));
+        let reply = server.run(request_reply(&config, &sample_prompt(), &FAST_RETRY)).unwrap();
+        assert_eq!(reply.content, "好的");
+        assert_eq!(reply.usage.total(), 128);
+
+        let request = server.requests().remove(0);
+        assert_eq!(request.path, "/v1/messages");
+        assert_eq!(request.header("x-api-key"), Some("sk-test"));
+        assert_eq!(request.header("anthropic-version"), Some("2023-06-01"));
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["system"], sample_prompt().system);
+        assert_eq!(body["messages"][0]["content"][0]["type"], "text");
+        assert_eq!(body["top_k"], 40);
+
+        config.options.insert(String::from("max_tokens"), serde_json::Value::from(512));
+        assert_eq!(AnthropicProvider.validate(&config).unwrap_err().kind, ErrorKind::Config);
+    }
+
+    #[test]
+    fn test_stream_events() {
+        let provider = AnthropicProvider;
+        assert_eq!(provider.parse_stream_line("event: content_block_delta").unwrap(), StreamEvent::Ignore);
+        assert_eq!(provider.parse_stream_line(r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"好的"}}"#).unwrap(),
+            StreamEvent::Delta(String::from("好的")));
+        assert_eq!(provider.parse_stream_line(r#"data: {"type":"message_delta","delta":{"stop_reason":"end_turn"}}"#).unwrap(), StreamEvent::Ignore);
+        assert_eq!(provider.parse_stream_line(r#"data: {"type":"message_stop"}"#).unwrap(), StreamEvent::Done);
+        assert_eq!(provider.parse_usage(r#"data: {"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":25,"output_tokens":1}}}"#),
+            Some(Usage { prompt_tokens: 25, completion_tokens: 1, total_tokens: 0 }));
+        assert_eq!(provider.parse_usage(r#"data: {"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":15}}"#).unwrap().completion_tokens, 15);
+        assert_eq!(provider.parse_usage("event: message_delta"), None);
+        let err = provider.parse_stream_line(r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Server);
+    }
+
+    #[test]
+    fn test_error_body() {
+        let server = StubServer::start(vec![(401, r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#)]);
+        let err = server.run(request_reply(&test_model("anthropic", server.url()), &sample_prompt(), &FAST_RETRY)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Auth);
+        assert!(err.detail.starts_with("authentication_error"));
+    }
+}
@@ -0,0 +1,208 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use serde_json::json;
+use std::sync::Mutex;
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use reqwest::{Client, RequestBuilder};
+use crate::conf::ModelConfig;
+use super::openai::ChatRequest;
+use super::{ChatPrompt, ErrorKind, ProviderError, ReplyProvider, StreamEvent};
+
+const DEFAULT_BASE: &str = "https://open.bigmodel.cn";
+// 签名的有效期为30分钟，过期前1分钟重新签名
+const TOKEN_TTL_MS: u64 = 30 * 60 * 1000;
+const REFRESH_MARGIN_MS: u64 = 60 * 1000;
+
+pub fn system_clock() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+// 智谱的API Key格式为id.secret，需要在本地签发HS256的JWT作为鉴权凭证
+pub fn sign_token(api_key: &str, now_ms: u64, ttl_ms: u64) -> Result<String, ProviderError> {
+    let (id, secret) = api_key.trim().split_once('.')
+        .filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+        .ok_or_else(|| ProviderError::new(ErrorKind::Config, "智谱API Key格式不正确，应为id.secret"))?;
+    let header = json!({"alg": "HS256", "sign_type": "SIGN"});
+    let payload = json!({"api_key": id, "exp": now_ms + ttl_ms, "timestamp": now_ms});
+    let signing_input = format!("{}.{}", URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(payload.to_string()));
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|err| ProviderError::new(ErrorKind::Config, err.to_string()))?;
+    mac.update(signing_input.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+    Ok(format!("{}.{}", signing_input, signature))
+}
+
+// 错误码参考：https://open.bigmodel.cn/dev/api#error-code-v3
+fn classify(code: &str, status: u16) -> (ErrorKind, Option<&'static str>) {
+    match code {
+        "1000" | "1001" | "1002" | "1003" | "1004" => (ErrorKind::Auth, Some("智谱API Key鉴权失败，请检查API Key")),
+        "1113" => (ErrorKind::Quota, Some("智谱账户已欠费，请充值后重试")),
+        "1211" => (ErrorKind::Api, Some("智谱模型不存在，请检查模型名称")),
+        "1261" => (ErrorKind::Api, Some("聊天记录过长，超出了模型的上下文长度")),
+        "1301" => (ErrorKind::ContentFilter, Some("聊天内容触发了智谱的内容安全策略，无法产出建议")),
+        "1302" | "1303" => (ErrorKind::RateLimit, None),
+        "1304" => (ErrorKind::Quota, Some("智谱API今日调用次数已达上限")),
+        _ => (ErrorKind::from_status(status), None)
+    }
+}
+
+struct CachedToken {
+    api_key: String,
+    token: String,
+    expires_at: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApiError {
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GlmResponse {
+    #[serde(default)]
+    pub choices: Vec<super::openai::Choice>,
+    #[serde(default)]
+    pub error: Option<ApiError>,
+}
+
+pub struct GlmProvider {
+    clock: fn() -> u64,
+    cache: Mutex<Option<CachedToken>>,
+}
+
+impl GlmProvider {
+    pub fn new(clock: fn() -> u64) -> Self {
+        GlmProvider { clock, cache: Mutex::new(None) }
+    }
+
+    // 复用未过期的签名，API Key变更或即将过期时重新签发
+    fn token(&self, api_key: &str) -> Result<String, ProviderError> {
+        let now = (self.clock)();
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(cached) = cache.as_ref() {
+            if cached.api_key == api_key && now + REFRESH_MARGIN_MS < cached.expires_at {
+                return Ok(cached.token.clone());
+            }
+        }
+        let token = sign_token(api_key, now, TOKEN_TTL_MS)?;
+        *cache = Some(CachedToken {
+            api_key: String::from(api_key),
+            token: token.clone(),
+            expires_at: now + TOKEN_TTL_MS
+        });
+        Ok(token)
+    }
+}
+
+impl ReplyProvider for GlmProvider {
+    fn default_base(&self) -> &str {
+        DEFAULT_BASE
+    }
+
+    fn validate(&self, model: &ModelConfig) -> Result<(), ProviderError> {
+        super::check_options(model, super::openai::TYPED_FIELDS)?;
+        sign_token(&model.api_token, 0, TOKEN_TTL_MS).map(|_| ())
+    }
+
+    fn build_request(&self, client: &Client, model: &ModelConfig, prompt: &ChatPrompt, stream: bool) -> Result<RequestBuilder, ProviderError> {
+        let token = self.token(&model.api_token)?;
+        // 智谱要求随机度位于(0, 1)的开区间
+        let mut req_body = ChatRequest::new(model, prompt, stream);
+        req_body.temperature = req_body.temperature.clamp(0.01, 0.99);
+        Ok(client.post(super::endpoint(model, DEFAULT_BASE, "/api/paas/v4/chat/completions"))
+        .json(&req_body).bearer_auth(token))
+    }
+
+    fn parse_response(&self, status: u16, body: &str) -> Result<String, ProviderError> {
+        let resp: GlmResponse = match serde_json::from_str(body) {
+            Ok(resp) => resp,
+            Err(_) if !(200..300).contains(&status) => {
+                return Err(ProviderError::from_status(status, format!("HTTP {}：{}", status, body)));
+            }
+            Err(err) => { return Err(ProviderError::new(ErrorKind::Parse, err.to_string())); }
+        };
+        if let Some(error) = resp.error {
+            let (kind, hint) = classify(&error.code, status);
+            let err = ProviderError::new(kind, format!("{}：{}", error.code, error.message));
+            return Err(match hint {
+                Some(hint) => err.with_hint(hint),
+                None => err
+            });
+        }
+        let contents: Vec<String> = resp.choices.into_iter()
+            .filter_map(|choice| choice.message.content).collect();
+        if contents.is_empty() {
+            Err(ProviderError::new(ErrorKind::Parse, "choices为空"))
+        } else {
+            Ok(contents.join("\n"))
+        }
+    }
+
+    fn parse_stream_line(&self, line: &str) -> Result<StreamEvent, ProviderError> {
+        super::openai::parse_stream_line(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use super::{sign_token, GlmProvider, TOKEN_TTL_MS};
+    use crate::provider::stub::{sample_prompt, StubServer};
+    use crate::provider::{ErrorKind, ReplyProvider};
+    use crate::testutil::test_model;
+
+    static NOW: AtomicU64 = AtomicU64::new(1_700_000_000_000);
+
+    fn fixed_clock() -> u64 {
+        NOW.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_sign_token() {
+        let token = sign_token("abc123.secretkey", 1_700_000_000_000, TOKEN_TTL_MS).unwrap();
+        assert_eq!(token, "eyJhbGciOiJIUzI1NiIsInNpZ25fdHlwZSI6IlNJR04ifQ.\
+            eyJhcGlfa2V5IjoiYWJjMTIzIiwiZXhwIjoxNzAwMDAxODAwMDAwLCJ0aW1lc3RhbXAiOjE3MDAwMDAwMDAwMDB9.\
+            PiGLk3RKLxnKTt4yows6PUzLPDmb7X3XqXWiNVDbxGI");
+        assert_eq!(sign_token("abc123", 0, TOKEN_TTL_MS).unwrap_err().kind, ErrorKind::Config);
+    }
+
+    #[test]
+    fn test_token_cache() {
+        let provider = GlmProvider::new(fixed_clock);
+        let first = provider.token("abc123.secretkey").unwrap();
+        NOW.fetch_add(10 * 60 * 1000, Ordering::SeqCst);
+        assert_eq!(provider.token("abc123.secretkey").unwrap(), first);
+        let second = provider.token("def456.secretkey").unwrap();
+        assert_ne!(second, first);
+        NOW.fetch_add(TOKEN_TTL_MS, Ordering::SeqCst);
+        assert_ne!(provider.token("def456.secretkey").unwrap(), second);
+    }
+
+    #[test]
+    fn test_error_code() {
+        let server = StubServer::start(vec![(429, r#"{"error":{"code":"1113","message":"您的账户已欠费，请充值后重试。"}}"#)]);
+        let mut config = test_model("glm", server.url());
+        config.temperature = 100;
+        config.api_token = String::from("abc123.secretkey");
+        let provider = GlmProvider::new(fixed_clock);
+        let request = provider.build_request(&reqwest::Client::new(), &config, &sample_prompt(), false).unwrap();
+        let resp = server.run(async { request.send().await.unwrap().text().await.unwrap() });
+        let err = provider.parse_response(429, &resp).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Quota);
+        assert_eq!(err.user_message(), "智谱账户已欠费，请充值后重试");
+
+        let request = server.requests().remove(0);
+        assert_eq!(request.path, "/api/paas/v4/chat/completions");
+        assert!(request.header("authorization").unwrap().starts_with("Bearer eyJ"));
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["temperature"].as_f64().unwrap() as f32, 0.99);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use reqwest::{Client, RequestBuilder};
+use crate::auto::WechatHistory;
+use crate::conf::ModelConfig;
+use super::{ChatPrompt, ErrorKind, ProviderError, ReplyProvider, StreamEvent};
+
+const DEFAULT_BASE: &str = "https://api.minimax.chat";
+const DEFAULT_MAX_TOKENS: u32 = 2048;
+
+#[derive(Serialize, Debug)]
+pub struct ApiRequest<'a, T> {
+    pub model: String,
+    pub temperature: f32,
+    pub stream: bool,
+    pub messages: &'a Vec<T>,
+    pub tokens_to_generate: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    pub reply_constraints: ReplyConstraints<'a>,
+    pub bot_setting: Vec<&'a HashMap<&'a str, &'a str>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReplyConstraints<'a> {
+    pub sender_type: &'a str,
+    pub sender_name: &'a str,
+}
+
+impl<'a> ReplyConstraints<'a> {
+    pub fn new_minimax(bot_name: &'a str) -> Self {
+        ReplyConstraints {
+            sender_type: "BOT",
+            sender_name: bot_name
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApiResponseBase {
+    pub status_code: i32,
+    pub status_msg: String
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApiResponse {
+    #[serde(default)]
+    pub reply: String,
+    pub base_resp: ApiResponseBase
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamMessage {
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamChoice {
+    #[serde(default)]
+    pub messages: Vec<StreamMessage>,
+}
+
+// 流式输出的最后一个数据包会带上完整的reply，此时不再重复追加
+#[derive(Deserialize, Debug)]
+pub struct StreamChunk {
+    #[serde(default)]
+    pub reply: String,
+    #[serde(default)]
+    pub choices: Vec<StreamChoice>,
+    #[serde(default)]
+    pub base_resp: Option<ApiResponseBase>,
+}
+
+// 错误码参考：https://api.minimax.chat/document/guides/error-code
+fn classify(status_code: i32) -> ErrorKind {
+    match status_code {
+        1004 | 2049 => ErrorKind::Auth,
+        1008 => ErrorKind::Quota,
+        1002 | 1039 => ErrorKind::RateLimit,
+        1026 | 1027 => ErrorKind::ContentFilter,
+        1000 | 1001 | 1013 | 1024 => ErrorKind::Server,
+        _ => ErrorKind::Api
+    }
+}
+
+fn base_error(base: ApiResponseBase) -> ProviderError {
+    ProviderError::new(classify(base.status_code), format!("{}：{}", base.status_code, base.status_msg))
+}
+
+pub struct MiniMaxProvider;
+
+impl ReplyProvider for MiniMaxProvider {
+    fn default_base(&self) -> &str {
+        DEFAULT_BASE
+    }
+
+    fn validate(&self, model: &ModelConfig) -> Result<(), ProviderError> {
+        if model.api_group.is_empty() || model.api_token.is_empty() {
+            Err(ProviderError::new(ErrorKind::Config, "请填写模型API相关配置"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn build_request(&self, client: &Client, model: &ModelConfig, prompt: &ChatPrompt, stream: bool) -> Result<RequestBuilder, ProviderError> {
+        self.validate(model)?;
+        let model_name = model.name.to_lowercase();
+
+        let mut bot_settings = HashMap::new();
+        bot_settings.insert("content", prompt.system.as_str());
+        bot_settings.insert("bot_name", prompt.bot_name.as_str());
+
+        let mut messages = prompt.history.clone();
+        messages.push(WechatHistory {
+            sender_type: String::from("USER"),
+            sender_name: prompt.nick.clone(),
+            text: prompt.instruction.clone()
+        });
+        let req_body = ApiRequest::<WechatHistory> {
+            model: model_name,
+            messages: &messages,
+            temperature: model.temperature as f32 / 100.0,
+            stream,
+            tokens_to_generate: model.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            top_p: model.top_p,
+            bot_setting: vec![&bot_settings],
+            reply_constraints: ReplyConstraints::new_minimax(&prompt.bot_name)
+        };
+
+        let request_url = format!("{}?GroupId={}", super::endpoint(
+            model, DEFAULT_BASE, "/v1/text/chatcompletion_pro"), &model.api_group);
+        Ok(client.post(request_url).json(&req_body)
+        .header("Authorization", format!("Bearer {}", model.api_token))
+        .header("Content-Type", "application/json"))
+    }
+
+    fn parse_response(&self, status: u16, body: &str) -> Result<String, ProviderError> {
+        let resp: ApiResponse = match serde_json::from_str(body) {
+            Ok(resp) => resp,
+            Err(_) if !(200..300).contains(&status) => {
+                return Err(ProviderError::from_status(status, format!("HTTP {}：{}", status, body)));
+            }
+            Err(err) => { return Err(ProviderError::new(ErrorKind::Parse, err.to_string())); }
+        };
+        if resp.base_resp.status_code == 0 {
+            Ok(resp.reply)
+        } else {
+            Err(base_error(resp.base_resp))
+        }
+    }
+
+    fn parse_stream_line(&self, line: &str) -> Result<StreamEvent, ProviderError> {
+        let Some(data) = super::sse_data(line) else { return Ok(StreamEvent::Ignore); };
+        let Ok(chunk) = serde_json::from_str::<StreamChunk>(data) else { return Ok(StreamEvent::Ignore); };
+        if let Some(base) = chunk.base_resp.filter(|base| base.status_code != 0) {
+            return Err(base_error(base));
+        }
+        if !chunk.reply.is_empty() {
+            return Ok(StreamEvent::Done);
+        }
+        let text: String = chunk.choices.iter().flat_map(|choice| choice.messages.iter())
+            .map(|message| message.text.as_str()).collect();
+        Ok(if text.is_empty() { StreamEvent::Ignore } else { StreamEvent::Delta(text) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MiniMaxProvider;
+    use crate::provider::stub::{sample_prompt, StubServer, FAST_RETRY};
+    use crate::provider::{request_reply_stream, ErrorKind, ReplyProvider};
+    use crate::testutil::test_model;
+
+    #[test]
+    fn test_request_mapping() {
+        let server = StubServer::start(vec![(200, r#"{"reply":"好的\n没问题","usage":{"total_tokens":96},"base_resp":{"status_code":0,"status_msg":""}}"#)]);
+        let mut config = test_model("minimax", server.url());
+        config.max_tokens = Some(1024);
+        let reply = server.run(crate::provider::request_reply(&config, &sample_prompt(), &FAST_RETRY)).unwrap();
+        assert_eq!(reply.content, "好的\n没问题");
+        assert_eq!(reply.usage.total(), 96);
+
+        let request = server.requests().remove(0);
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/v1/text/chatcompletion_pro?GroupId=group");
+        assert_eq!(request.header("authorization"), Some("Bearer sk-test"));
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["model"], "abab6-chat");
+        assert_eq!(body["tokens_to_generate"], 1024);
+        assert_eq!(body["reply_constraints"]["sender_type"], "BOT");
+        assert_eq!(body["messages"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_stream() {
+        let server = StubServer::start(vec![(200, "data: {\"reply\":\"\",\"choices\":[{\"messages\":[{\"sender_type\":\"BOT\",\"text\":\"好的\\n\"}]}]}\n\n\
+            data: {\"reply\":\"\",\"choices\":[{\"messages\":[{\"sender_type\":\"BOT\",\"text\":\"没问题\"}]}]}\n\n\
+            data: {\"reply\":\"好的\\n没问题\",\"choices\":[{\"messages\":[{\"sender_type\":\"BOT\",\"text\":\"好的\\n没问题\"}]}],\"base_resp\":{\"status_code\":0,\"status_msg\":\"\"}}\n\n")]);
+        let reply = server.run(request_reply_stream(&test_model("minimax", server.url()), &sample_prompt(), &FAST_RETRY, |_| {}));
+        assert_eq!(reply.unwrap().content, "好的\n没问题");
+    }
+
+    #[test]
+    fn test_error_translation() {
+        let provider = MiniMaxProvider;
+        let err = provider.parse_response(200, r#"{"base_resp":{"status_code":1004,"status_msg":"auth failed"}}"#).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Auth);
+        let err = provider.parse_response(200, r#"{"base_resp":{"status_code":1002,"status_msg":"rate limit"}}"#).unwrap_err();
+        assert!(err.is_retryable());
+        let err = provider.parse_response(200, r#"{"base_resp":{"status_code":1027,"status_msg":"output new_sensitive"}}"#).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::ContentFilter);
+        let err = provider.parse_response(502, "Bad Gateway").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Server);
+        let err = provider.parse_response(200, "{").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Parse);
+    }
+
+    #[test]
+    fn test_missing_group() {
+        let mut config = test_model("minimax", String::new());
+        config.api_group = String::new();
+        let err = MiniMaxProvider.build_request(&reqwest::Client::new(), &config, &sample_prompt(), false).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Config);
+    }
+}
@@ -0,0 +1,622 @@
+mod minimax;
+mod openai;
+mod ollama;
+mod anthropic;
+mod glm;
+#[cfg(test)]
+pub(crate) mod stub;
+
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use std::collections::HashMap;
+use crate::auto::WechatHistory;
+use serde::{Deserialize, Serialize};
+use crate::conf::{ModelConfig, NetworkConfig};
+use reqwest::{Certificate, Client, NoProxy, Proxy, RequestBuilder};
+
+static CLIENT: Mutex<Option<Client>> = Mutex::new(None);
+static REGISTRY: OnceLock<HashMap<&'static str, Box<dyn ReplyProvider>>> = OnceLock::new();
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+// 流式输出时两段数据之间的最长间隔
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Config,
+    Auth,
+    Quota,
+    RateLimit,
+    ContentFilter,
+    Network,
+    Server,
+    Api,
+    Parse,
+}
+
+impl ErrorKind {
+    // 按HTTP状态码做初步分类，各个Provider可以根据错误码进一步细化
+    pub fn from_status(status: u16) -> ErrorKind {
+        match status {
+            401 | 403 => ErrorKind::Auth,
+            402 => ErrorKind::Quota,
+            408 => ErrorKind::Network,
+            429 => ErrorKind::RateLimit,
+            500..=599 => ErrorKind::Server,
+            _ => ErrorKind::Api,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProviderError {
+    pub kind: ErrorKind,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl ProviderError {
+    pub fn new(kind: ErrorKind, detail: impl Into<String>) -> Self {
+        ProviderError { kind, detail: detail.into(), hint: None }
+    }
+
+    // 厂商能明确错误原因时，附带可以直接展示给用户的提示
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn from_status(status: u16, detail: impl Into<String>) -> Self {
+        ProviderError::new(ErrorKind::from_status(status), detail)
+    }
+
+    // 限流、服务端错误和网络错误可以重试
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind, ErrorKind::RateLimit | ErrorKind::Server | ErrorKind::Network)
+    }
+
+    // 网络、额度和服务端的问题换一个模型可能就能解决，鉴权、内容安全等问题则不行
+    pub fn should_fallback(&self) -> bool {
+        matches!(self.kind, ErrorKind::Network | ErrorKind::Quota | ErrorKind::RateLimit | ErrorKind::Server)
+    }
+
+    // 返回给用户的提示信息，配置类错误直接透出具体原因
+    pub fn user_message(&self) -> String {
+        if let Some(hint) = &self.hint {
+            return hint.clone();
+        }
+        match self.kind {
+            ErrorKind::Config => self.detail.clone(),
+            ErrorKind::Auth => String::from("API Key无效或没有权限，请在设置中检查模型API配置"),
+            ErrorKind::Quota => String::from("模型账户余额或额度不足，请充值后重试"),
+            ErrorKind::RateLimit => String::from("请求过于频繁，已被模型服务限流，请稍后重试"),
+            ErrorKind::ContentFilter => String::from("聊天内容触发了模型的内容安全策略，无法产出建议"),
+            ErrorKind::Network => String::from("网络请求失败，请检查网络后重试"),
+            ErrorKind::Server => String::from("模型服务暂时不可用，请稍后重试"),
+            ErrorKind::Api => String::from("获取回复失败，请稍后重试"),
+            ErrorKind::Parse => String::from("解析回复内容失败，请稍后重试"),
+        }
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.detail)
+    }
+}
+
+// 与具体厂商无关的对话请求，由各个Provider自行转换为接口格式
+#[derive(Debug, Clone)]
+pub struct ChatPrompt {
+    pub nick: String,
+    pub bot_name: String,
+    pub system: String,
+    pub history: Vec<WechatHistory>,
+    pub instruction: String,
+    // 要求模型输出JSON，支持JSON模式的厂商会打开对应的参数
+    pub json_output: bool,
+}
+
+// 一次调用消耗的token数，部分厂商只返回总数
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    #[serde(default, alias = "input_tokens")]
+    pub prompt_tokens: u32,
+    #[serde(default, alias = "output_tokens")]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+}
+
+impl Usage {
+    pub fn total(&self) -> u32 {
+        self.total_tokens.max(self.prompt_tokens + self.completion_tokens)
+    }
+
+    // 流式输出时用量分散在多个数据包中，以后到的非零值为准
+    pub fn merge(&mut self, other: Usage) {
+        if other.prompt_tokens > 0 { self.prompt_tokens = other.prompt_tokens; }
+        if other.completion_tokens > 0 { self.completion_tokens = other.completion_tokens; }
+        if other.total_tokens > 0 { self.total_tokens = other.total_tokens; }
+    }
+}
+
+#[derive(Deserialize)]
+struct UsageBody {
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+// 读取响应或流式数据包顶层的usage字段，OpenAI兼容接口、智谱和MiniMax都采用这种格式
+pub fn usage_field(body: &str) -> Option<Usage> {
+    let json = sse_data(body).unwrap_or(body);
+    serde_json::from_str::<UsageBody>(json).ok().and_then(|body| body.usage)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub content: String,
+    pub usage: Usage,
+}
+
+// 备用模型链中实际给出回复的模型
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainReply {
+    pub content: String,
+    pub provider: String,
+    pub model: String,
+    pub usage: Usage,
+}
+
+// 流式输出时，每一行数据解析出的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    Delta(String),
+    Done,
+    Ignore,
+}
+
+pub trait ReplyProvider: Send + Sync {
+    // 未配置接口地址时使用的官方地址
+    fn default_base(&self) -> &str;
+    // 校验模型配置是否满足该厂商的要求，保存配置时调用
+    fn validate(&self, model: &ModelConfig) -> Result<(), ProviderError>;
+    // 根据模型配置和对话内容构造HTTP请求，stream为true时请求流式输出
+    fn build_request(&self, client: &Client, model: &ModelConfig, prompt: &ChatPrompt, stream: bool) -> Result<RequestBuilder, ProviderError>;
+    // 将接口返回的内容转换为回复文本，或转换为对应的错误
+    fn parse_response(&self, status: u16, body: &str) -> Result<String, ProviderError>;
+    // 解析流式输出中的一行（SSE的data行或NDJSON的一行）
+    fn parse_stream_line(&self, line: &str) -> Result<StreamEvent, ProviderError>;
+    // 从完整的响应或流式输出的一行中读取token用量，没有用量信息时返回None
+    fn parse_usage(&self, body: &str) -> Option<Usage> {
+        usage_field(body)
+    }
+}
+
+fn registry() -> &'static HashMap<&'static str, Box<dyn ReplyProvider>> {
+    REGISTRY.get_or_init(|| {
+        let mut providers: HashMap<&'static str, Box<dyn ReplyProvider>> = HashMap::new();
+        providers.insert("MINIMAX", Box::new(minimax::MiniMaxProvider));
+        providers.insert("OPENAI", Box::new(openai::OpenAiProvider::OPENAI));
+        providers.insert("LLAMACPP", Box::new(openai::OpenAiProvider::LLAMA_CPP));
+        providers.insert("OLLAMA", Box::new(ollama::OllamaProvider));
+        providers.insert("ANTHROPIC", Box::new(anthropic::AnthropicProvider));
+        providers.insert("GLM", Box::new(glm::GlmProvider::new(glm::system_clock)));
+        providers
+    })
+}
+
+pub fn provider_for(name: &str) -> Option<&'static dyn ReplyProvider> {
+    registry().get(name.trim().to_uppercase().as_str()).map(|p| p.as_ref())
+}
+
+// 额外参数会原样合并进请求体，与已有字段重名时要求直接修改对应的配置项
+pub fn check_options(model: &ModelConfig, typed_fields: &[&str]) -> Result<(), ProviderError> {
+    match model.options.keys().find(|key| typed_fields.contains(&key.as_str())) {
+        Some(key) => Err(ProviderError::new(ErrorKind::Config, format!("额外参数{}与内置参数重复，请直接修改对应的配置项", key))),
+        None => Ok(()),
+    }
+}
+
+// 拼接接口地址，配置了base_url时优先使用配置的地址
+pub fn endpoint(model: &ModelConfig, default_base: &str, path: &str) -> String {
+    let base = if model.base_url.trim().is_empty() { default_base } else { model.base_url.trim() };
+    format!("{}{}", base.trim_end_matches('/'), path)
+}
+
+fn find_provider(model: &ModelConfig) -> Result<&'static dyn ReplyProvider, ProviderError> {
+    provider_for(&model.provider).ok_or_else(|| ProviderError::new(
+        ErrorKind::Config, format!("不支持的模型提供商：{}", model.provider)))
+}
+
+pub fn validate_model(model: &ModelConfig) -> Result<(), ProviderError> {
+    find_provider(model)?.validate(model)
+}
+
+// 取出SSE中data行的内容，其他行（event、注释、空行）返回None
+pub fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(|data| data.trim())
+}
+
+fn network_error(err: reqwest::Error) -> ProviderError {
+    let error = ProviderError::new(ErrorKind::Network, err.to_string());
+    if err.is_timeout() {
+        error.with_hint("网络请求超时，请检查网络后重试")
+    } else {
+        error
+    }
+}
+
+fn timeout_error() -> ProviderError {
+    ProviderError::new(ErrorKind::Network, "读取超时").with_hint("网络请求超时，请检查网络后重试")
+}
+
+// 按网络配置创建Client：代理支持http、https和socks5，可以附加企业内网的根证书
+pub fn build_client(network: &NetworkConfig) -> Result<Client, ProviderError> {
+    let mut builder = Client::builder().connect_timeout(CONNECT_TIMEOUT);
+    if !network.proxy.trim().is_empty() {
+        let mut proxy = Proxy::all(network.proxy.trim()).map_err(|err|
+            ProviderError::new(ErrorKind::Config, format!("代理地址格式不正确：{}", err)))?;
+        if !network.proxy_username.is_empty() {
+            proxy = proxy.basic_auth(&network.proxy_username, &network.proxy_password);
+        }
+        builder = builder.proxy(proxy.no_proxy(NoProxy::from_string(&network.no_proxy.join(","))));
+    }
+    for path in network.ca_certs.iter().filter(|path| !path.trim().is_empty()) {
+        let pem = std::fs::read(path.trim()).map_err(|err|
+            ProviderError::new(ErrorKind::Config, format!("读取根证书{}失败：{}", path, err)))?;
+        let certs = Certificate::from_pem_bundle(&pem).ok().filter(|certs| !certs.is_empty())
+            .ok_or_else(|| ProviderError::new(ErrorKind::Config, format!("根证书{}不是有效的PEM格式", path)))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    builder.build().map_err(|err| ProviderError::new(ErrorKind::Config, format!("创建网络连接失败：{}", err)))
+}
+
+// 网络配置变更后替换共用的Client，旧的连接池随之释放
+pub fn configure_network(network: &NetworkConfig) -> Result<(), ProviderError> {
+    let client = build_client(network)?;
+    *CLIENT.lock().unwrap() = Some(client);
+    Ok(())
+}
+
+// 所有请求共用一个Client，复用连接池
+fn client() -> Client {
+    CLIENT.lock().unwrap().get_or_insert_with(|| build_client(&NetworkConfig::default())
+        .unwrap_or_default()).clone()
+}
+
+// 实际会发送的请求，不包含鉴权信息，供设置页面预览
+#[derive(Serialize, Debug)]
+pub struct RequestPreview {
+    pub url: String,
+    pub body: serde_json::Value,
+}
+
+pub fn preview_request(model: &ModelConfig, prompt: &ChatPrompt) -> Result<RequestPreview, ProviderError> {
+    let request = find_provider(model)?.build_request(&client(), model, prompt, model.stream)?
+        .build().map_err(|err| ProviderError::new(ErrorKind::Config, err.to_string()))?;
+    let body = request.body().and_then(|body| body.as_bytes())
+        .and_then(|bytes| serde_json::from_slice(bytes).ok()).unwrap_or_default();
+    Ok(RequestPreview { url: request.url().to_string(), body })
+}
+
+// 用给定的网络配置访问模型的接口地址，能收到HTTP响应即说明代理和证书配置可用
+pub async fn check_network(network: &NetworkConfig, model: &ModelConfig) -> Result<u16, ProviderError> {
+    let client = build_client(network)?;
+    let url = endpoint(model, find_provider(model)?.default_base(), "");
+    let resp = client.get(url).timeout(REQUEST_TIMEOUT).send().await.map_err(network_error)?;
+    match resp.status().as_u16() {
+        407 => Err(ProviderError::new(ErrorKind::Auth, "HTTP 407").with_hint("代理服务器鉴权失败，请检查代理的用户名和密码")),
+        status => Ok(status),
+    }
+}
+
+// 可以重试的错误最多请求max_attempts次，每次重试前等待的时间从base_delay开始翻倍
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub const STANDARD: RetryPolicy = RetryPolicy { max_attempts: 3, base_delay: Duration::from_millis(500) };
+
+    fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay * (1 << attempt)
+    }
+}
+
+// 可以重试时按指数退避等待后返回true
+async fn wait_for_retry(err: &ProviderError, attempt: u32, retry: &RetryPolicy) -> bool {
+    if !err.is_retryable() || attempt + 1 >= retry.max_attempts {
+        return false;
+    }
+    println!("请求失败，准备第{}次重试，err_msg：{}", attempt + 1, err);
+    tokio::time::sleep(retry.delay(attempt)).await;
+    true
+}
+
+async fn send_once(provider: &dyn ReplyProvider, model: &ModelConfig, prompt: &ChatPrompt) -> Result<Reply, ProviderError> {
+    let request = provider.build_request(&client(), model, prompt, false)?;
+    let resp = request.timeout(REQUEST_TIMEOUT).send().await.map_err(network_error)?;
+    let status = resp.status().as_u16();
+    let body = resp.text().await.map_err(network_error)?;
+    let content = provider.parse_response(status, &body)?;
+    Ok(Reply { content, usage: provider.parse_usage(&body).unwrap_or_default() })
+}
+
+pub async fn request_reply(model: &ModelConfig, prompt: &ChatPrompt, retry: &RetryPolicy) -> Result<Reply, ProviderError> {
+    let provider = find_provider(model)?;
+    let mut attempt = 0;
+    loop {
+        match send_once(provider, model, prompt).await {
+            Err(err) if wait_for_retry(&err, attempt, retry).await => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+async fn stream_once<F: FnMut(&str)>(provider: &dyn ReplyProvider, model: &ModelConfig, prompt: &ChatPrompt, on_delta: &mut F) -> Result<Reply, ProviderError> {
+    let request = provider.build_request(&client(), model, prompt, true)?;
+    let mut resp = tokio::time::timeout(REQUEST_TIMEOUT, request.send()).await
+        .map_err(|_| timeout_error())?.map_err(network_error)?;
+    let status = resp.status().as_u16();
+    if !(200..300).contains(&status) {
+        let body = resp.text().await.map_err(network_error)?;
+        let content = provider.parse_response(status, &body)?;
+        return Ok(Reply { content, usage: provider.parse_usage(&body).unwrap_or_default() });
+    }
+
+    let mut raw = String::new();
+    let mut reply = String::new();
+    let mut usage = Usage::default();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut finished = false;
+    while !finished {
+        let chunk = tokio::time::timeout(READ_TIMEOUT, resp.chunk()).await
+            .map_err(|_| timeout_error())?.map_err(network_error)?;
+        match chunk {
+            Some(bytes) => buffer.extend_from_slice(&bytes),
+            None => { finished = true; buffer.push(b'\n'); }
+        }
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line_bytes: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line_bytes);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            raw.push_str(line);
+            raw.push('\n');
+            if let Some(line_usage) = provider.parse_usage(line) {
+                usage.merge(line_usage);
+            }
+            match provider.parse_stream_line(line)? {
+                StreamEvent::Delta(text) => {
+                    on_delta(&text);
+                    reply.push_str(&text);
+                }
+                StreamEvent::Done => { finished = true; break; }
+                StreamEvent::Ignore => {}
+            }
+        }
+    }
+
+    // 部分兼容服务会忽略stream参数，直接返回完整的结果
+    if reply.is_empty() && !raw.is_empty() {
+        let content = provider.parse_response(status, &raw)?;
+        on_delta(&content);
+        Ok(Reply { content, usage: provider.parse_usage(&raw).unwrap_or(usage) })
+    } else {
+        Ok(Reply { content: reply, usage })
+    }
+}
+
+// 流式获取回复，每收到一段增量内容就回调on_delta，结束后返回完整的回复。
+// 已经输出过内容后再失败不会重试，避免工具窗口中出现重复的建议
+pub async fn request_reply_stream<F: FnMut(&str)>(model: &ModelConfig, prompt: &ChatPrompt, retry: &RetryPolicy, mut on_delta: F)
+-> Result<Reply, ProviderError> {
+    let provider = find_provider(model)?;
+    let mut attempt = 0;
+    loop {
+        let mut received = false;
+        let mut forward = |delta: &str| {
+            received = true;
+            on_delta(delta);
+        };
+        match stream_once(provider, model, prompt, &mut forward).await {
+            Err(err) if !received && wait_for_retry(&err, attempt, retry).await => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+// 按顺序尝试主模型和备用模型，可以换模型解决的错误才继续尝试下一个。
+// 已经输出过内容后再失败不再切换，避免不同模型的建议混在一起
+pub async fn request_reply_chain<F: FnMut(&str)>(attempts: &[(ModelConfig, ChatPrompt)], retry: &RetryPolicy, mut on_delta: F)
+-> Result<ChainReply, ProviderError> {
+    let mut last_err = ProviderError::new(ErrorKind::Config, "没有可用的模型配置");
+    for (model, prompt) in attempts {
+        let mut received = false;
+        let resp = if model.stream {
+            request_reply_stream(model, prompt, retry, |delta| {
+                received = true;
+                on_delta(delta);
+            }).await
+        } else {
+            request_reply(model, prompt, retry).await
+        };
+        match resp {
+            Ok(reply) => {
+                return Ok(ChainReply {
+                    content: reply.content,
+                    provider: model.provider.clone(),
+                    model: model.name.clone(),
+                    usage: reply.usage
+                });
+            }
+            Err(err) if !received && err.should_fallback() => {
+                println!("{}/{}请求失败，尝试下一个模型，err_msg：{}", model.provider, model.name, err);
+                last_err = err;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(last_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+    use super::{build_client, check_network, preview_request, provider_for, request_reply_chain, ErrorKind, RetryPolicy};
+    use super::stub::{sample_prompt, StubServer, FAST_RETRY};
+    use crate::conf::{ModelConfig, NetworkConfig};
+    use crate::testutil::{temp_path, test_model};
+
+    const OPENAI_REPLY: &str = r#"{"choices":[{"message":{"content":"好的"}}]}"#;
+
+    fn model(provider: &str, base_url: String, stream: bool) -> ModelConfig {
+        ModelConfig { stream, ..test_model(provider, base_url) }
+    }
+
+    // 取一个没有服务监听的端口，连接会被直接拒绝
+    fn closed_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port())
+    }
+
+    #[test]
+    fn test_fallback_on_quota_and_network() {
+        let quota = StubServer::start(vec![(402, r#"{"error":{"message":"Insufficient Balance","type":"unknown_error"}}"#)]);
+        let backup = StubServer::start(vec![(200, OPENAI_REPLY)]);
+        let attempts = vec![
+            (model("openai", quota.url(), false), sample_prompt()),
+            (model("openai", closed_url(), false), sample_prompt()),
+            (model("llamacpp", backup.url(), false), sample_prompt()),
+        ];
+        let reply = backup.run(request_reply_chain(&attempts, &FAST_RETRY, |_| {})).unwrap();
+        assert_eq!(reply.content, "好的");
+        assert_eq!(reply.provider, "llamacpp");
+        assert_eq!(quota.requests().len(), 1);
+        assert_eq!(backup.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_fallback_after_server_errors() {
+        let primary = StubServer::start(vec![(500, "oops"), (502, "Bad Gateway"), (503, "Service Unavailable")]);
+        let backup = StubServer::start(vec![(200, "{\"message\":{\"content\":\"好\"},\"done\":false}\n\
+            {\"message\":{\"content\":\"的\"},\"done\":true}\n")]);
+        let attempts = vec![
+            (model("openai", primary.url(), true), sample_prompt()),
+            (model("ollama", backup.url(), true), sample_prompt()),
+        ];
+        let mut deltas = Vec::new();
+        let reply = backup.run(request_reply_chain(&attempts, &FAST_RETRY, |delta| deltas.push(String::from(delta)))).unwrap();
+        assert_eq!(reply.content, "好的");
+        assert_eq!(reply.model, "qwen2:7b");
+        assert_eq!(deltas, vec!["好", "的"]);
+        assert_eq!(primary.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_no_fallback_on_auth() {
+        let primary = StubServer::start(vec![(401, r#"{"error":{"message":"Incorrect API key","type":"invalid_request_error"}}"#)]);
+        let backup = StubServer::start(vec![(200, OPENAI_REPLY)]);
+        let attempts = vec![
+            (model("openai", primary.url(), false), sample_prompt()),
+            (model("openai", backup.url(), false), sample_prompt()),
+        ];
+        let err = primary.run(request_reply_chain(&attempts, &FAST_RETRY, |_| {})).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Auth);
+        assert!(backup.requests().is_empty());
+    }
+
+    #[test]
+    fn test_chain_exhausted() {
+        let first = StubServer::start(vec![(402, r#"{"error":{"message":"Insufficient Balance"}}"#)]);
+        let attempts = vec![
+            (model("openai", first.url(), false), sample_prompt()),
+            (model("openai", closed_url(), true), sample_prompt()),
+        ];
+        let err = first.run(request_reply_chain(&attempts, &FAST_RETRY, |_| {})).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Network);
+        assert_eq!(first.run(request_reply_chain(&[], &FAST_RETRY, |_| {})).unwrap_err().kind, ErrorKind::Config);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(RetryPolicy::STANDARD.delay(0), Duration::from_millis(500));
+        assert_eq!(RetryPolicy::STANDARD.delay(2), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_http_proxy_auth() {
+        let proxy = StubServer::start(vec![(200, OPENAI_REPLY)]);
+        let network = NetworkConfig {
+            proxy: proxy.url(),
+            proxy_username: String::from("alice"),
+            proxy_password: String::from("secret"),
+            ..Default::default()
+        };
+        let client = build_client(&network).unwrap();
+        let config = model("openai", String::from("http://api.example.test"), false);
+        let request = provider_for("openai").unwrap().build_request(&client, &config, &sample_prompt(), false).unwrap();
+        assert_eq!(proxy.run(async { request.send().await.unwrap().status().as_u16() }), 200);
+
+        let request = proxy.requests().remove(0);
+        assert_eq!(request.path, "http://api.example.test/v1/chat/completions");
+        assert_eq!(request.header("proxy-authorization"), Some("Basic YWxpY2U6c2VjcmV0"));
+    }
+
+    #[test]
+    fn test_no_proxy() {
+        let server = StubServer::start(vec![(200, OPENAI_REPLY)]);
+        let network = NetworkConfig {
+            proxy: closed_url(),
+            no_proxy: vec![String::from("127.0.0.1")],
+            ..Default::default()
+        };
+        let client = build_client(&network).unwrap();
+        let request = provider_for("openai").unwrap().build_request(&client,
+            &model("openai", server.url(), false), &sample_prompt(), false).unwrap();
+        assert_eq!(server.run(async { request.send().await.unwrap().status().as_u16() }), 200);
+        assert_eq!(server.requests()[0].path, "/v1/chat/completions");
+    }
+
+    #[test]
+    fn test_invalid_network() {
+        let network = NetworkConfig { proxy: String::from("ftp://proxy.local:21"), ..Default::default() };
+        assert_eq!(build_client(&network).unwrap_err().kind, ErrorKind::Config);
+
+        let path = temp_path("invalid.pem");
+        std::fs::write(&path, "not a certificate").unwrap();
+        let network = NetworkConfig { ca_certs: vec![path.to_string_lossy().into_owned()], ..Default::default() };
+        assert!(build_client(&network).unwrap_err().detail.contains("PEM"));
+        let network = NetworkConfig { ca_certs: vec![String::from("/nonexistent/ca.pem")], ..Default::default() };
+        assert!(build_client(&network).unwrap_err().detail.contains("读取根证书"));
+    }
+
+    #[test]
+    fn test_check_network() {
+        let server = StubServer::start(vec![(404, "Not Found"), (407, "Proxy Authentication Required")]);
+        let config = model("openai", server.url(), false);
+        assert_eq!(server.run(check_network(&NetworkConfig::default(), &config)).unwrap(), 404);
+        let err = server.run(check_network(&NetworkConfig::default(), &config)).unwrap_err();
+        assert_eq!(err.user_message(), "代理服务器鉴权失败，请检查代理的用户名和密码");
+    }
+
+    #[test]
+    fn test_preview_request() {
+        let preview = preview_request(&model("openai", String::from("http://127.0.0.1:8000/v1"), true), &sample_prompt()).unwrap();
+        assert_eq!(preview.url, "http://127.0.0.1:8000/v1/chat/completions");
+        assert_eq!(preview.body["stream"], true);
+        assert_eq!(preview.body["messages"][0]["content"], sample_prompt().system);
+        let err = preview_request(&model("unknown", String::new(), false), &sample_prompt()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Config);
+    }
+}
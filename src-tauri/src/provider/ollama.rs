@@ -0,0 +1,171 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use reqwest::{Client, RequestBuilder};
+use crate::conf::ModelConfig;
+use super::openai::{map_messages, ChatMessage};
+use super::{ChatPrompt, ErrorKind, ProviderError, ReplyProvider, StreamEvent, Usage};
+
+const DEFAULT_BASE: &str = "http://127.0.0.1:11434";
+
+#[derive(Serialize, Debug)]
+pub struct OllamaRequest<'a> {
+    pub model: &'a str,
+    pub stream: bool,
+    pub messages: Vec<ChatMessage>,
+    pub options: HashMap<String, Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<&'static str>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaMessage {
+    #[serde(default)]
+    pub content: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaResponse {
+    #[serde(default)]
+    pub message: Option<OllamaMessage>,
+    #[serde(default)]
+    pub done: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub prompt_eval_count: u32,
+    #[serde(default)]
+    pub eval_count: u32,
+}
+
+// 本地运行的Ollama服务，不需要API Group和API Key
+pub struct OllamaProvider;
+
+impl ReplyProvider for OllamaProvider {
+    fn default_base(&self) -> &str {
+        DEFAULT_BASE
+    }
+
+    fn validate(&self, _model: &ModelConfig) -> Result<(), ProviderError> {
+        Ok(())
+    }
+
+    fn build_request(&self, client: &Client, model: &ModelConfig, prompt: &ChatPrompt, stream: bool) -> Result<RequestBuilder, ProviderError> {
+        // num_ctx等模型参数原样放入options，随机度以配置项为准
+        let mut options = model.options.clone();
+        options.insert(String::from("temperature"), Value::from(model.temperature as f32 / 100.0));
+        let sampling = [("num_predict", model.max_tokens.map(Value::from)), ("top_p", model.top_p.map(Value::from)),
+            ("presence_penalty", model.presence_penalty.map(Value::from)),
+            ("frequency_penalty", model.frequency_penalty.map(Value::from)),
+            ("stop", (!model.stop.is_empty()).then(|| Value::from(model.stop.clone())))];
+        for (key, value) in sampling {
+            if let Some(value) = value {
+                options.insert(String::from(key), value);
+            }
+        }
+        let req_body = OllamaRequest {
+            model: model.name.trim(),
+            stream,
+            messages: map_messages(prompt),
+            options,
+            format: prompt.json_output.then_some("json"),
+        };
+        let mut request = client.post(super::endpoint(model, DEFAULT_BASE, "/api/chat")).json(&req_body);
+        if !model.api_token.is_empty() {
+            request = request.bearer_auth(&model.api_token);
+        }
+        Ok(request)
+    }
+
+    fn parse_response(&self, status: u16, body: &str) -> Result<String, ProviderError> {
+        let resp: OllamaResponse = match serde_json::from_str(body) {
+            Ok(resp) => resp,
+            Err(_) if !(200..300).contains(&status) => {
+                return Err(ProviderError::from_status(status, format!("HTTP {}：{}", status, body)));
+            }
+            Err(err) => { return Err(ProviderError::new(ErrorKind::Parse, err.to_string())); }
+        };
+        match (resp.error, resp.message) {
+            (Some(error), _) => Err(ProviderError::from_status(status, format!("HTTP {}：{}", status, error))),
+            (None, Some(message)) => Ok(message.content),
+            (None, None) => Err(ProviderError::new(ErrorKind::Parse, "message为空")),
+        }
+    }
+
+    // 流式输出为NDJSON，每行一个完整的JSON对象，done为true时结束
+    fn parse_stream_line(&self, line: &str) -> Result<StreamEvent, ProviderError> {
+        let Ok(chunk) = serde_json::from_str::<OllamaResponse>(line) else { return Ok(StreamEvent::Ignore); };
+        if let Some(error) = chunk.error {
+            return Err(ProviderError::new(ErrorKind::Api, error));
+        }
+        match chunk.message {
+            Some(message) if !message.content.is_empty() => Ok(StreamEvent::Delta(message.content)),
+            _ if chunk.done => Ok(StreamEvent::Done),
+            _ => Ok(StreamEvent::Ignore),
+        }
+    }
+
+    // 用量只在done为true的最后一个对象中返回
+    fn parse_usage(&self, body: &str) -> Option<Usage> {
+        let resp = serde_json::from_str::<OllamaResponse>(body).ok().filter(|resp| resp.done)?;
+        Some(Usage { prompt_tokens: resp.prompt_eval_count, completion_tokens: resp.eval_count, total_tokens: 0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use crate::conf::ModelConfig;
+    use crate::provider::stub::{sample_prompt, StubServer, FAST_RETRY};
+    use crate::provider::{request_reply, request_reply_stream, validate_model, ErrorKind};
+    use crate::testutil::test_model;
+
+    // 本地模型不需要密钥
+    fn model(base_url: String) -> ModelConfig {
+        let mut config = test_model("ollama", base_url);
+        config.api_token = String::new();
+        config.options.insert(String::from("num_ctx"), Value::from(8192));
+        config.max_tokens = Some(512);
+        config
+    }
+
+    #[test]
+    fn test_local_chat() {
+        let server = StubServer::start(vec![(200, r#"{"model":"qwen2:7b","message":{"role":"assistant","content":"好的"},"done":true}"#)]);
+        let config = model(server.url());
+        assert!(validate_model(&config).is_ok());
+        let reply = server.run(request_reply(&config, &sample_prompt(), &FAST_RETRY));
+        assert_eq!(reply.unwrap().content, "好的");
+
+        let request = server.requests().remove(0);
+        assert_eq!(request.path, "/api/chat");
+        assert_eq!(request.header("authorization"), None);
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["format"], "json");
+        assert_eq!(body["options"]["num_ctx"], 8192);
+        assert_eq!(body["options"]["num_predict"], 512);
+        assert!(body["options"].get("top_p").is_none());
+        assert_eq!(body["messages"][2]["role"], "assistant");
+    }
+
+    #[test]
+    fn test_ndjson_stream() {
+        let server = StubServer::start(vec![(200, "{\"message\":{\"role\":\"assistant\",\"content\":\"好\"},\"done\":false}\n\
+            {\"message\":{\"role\":\"assistant\",\"content\":\"的\"},\"done\":false}\n\
+            {\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":26,\"eval_count\":2}\n")]);
+        let mut deltas = 0;
+        let reply = server.run(request_reply_stream(&model(server.url()), &sample_prompt(), &FAST_RETRY, |_| deltas += 1)).unwrap();
+        assert_eq!(reply.content, "好的");
+        assert_eq!(reply.usage.total(), 28);
+        assert_eq!(deltas, 2);
+    }
+
+    #[test]
+    fn test_model_not_found() {
+        let server = StubServer::start(vec![(404, r#"{"error":"model 'qwen2:7b' not found, try pulling it first"}"#)]);
+        let err = server.run(request_reply(&model(server.url()), &sample_prompt(), &FAST_RETRY)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Api);
+        assert!(err.detail.contains("not found"));
+    }
+}
@@ -0,0 +1,331 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use reqwest::{Client, RequestBuilder};
+use crate::conf::ModelConfig;
+use super::{ChatPrompt, ErrorKind, ProviderError, ReplyProvider, StreamEvent};
+
+const DEFAULT_BASE: &str = "https://api.openai.com";
+// 请求体中已有的字段，额外参数不能与之重名，否则序列化后会出现重复的键
+pub const TYPED_FIELDS: &[&str] = &["model", "temperature", "stream", "messages", "stream_options", "response_format",
+    "max_tokens", "top_p", "presence_penalty", "frequency_penalty", "stop"];
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ChatMessage {
+    pub role: &'static str,
+    pub content: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChatRequest<'a> {
+    pub model: &'a str,
+    pub temperature: f32,
+    pub stream: bool,
+    pub messages: Vec<ChatMessage>,
+    // 流式输出时要求在最后一个数据包中返回用量。不少兼容服务会拒绝未知字段，只对官方接口发送
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub stop: &'a [String],
+    #[serde(flatten)]
+    pub options: &'a HashMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChoiceMessage {
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Choice {
+    pub message: ChoiceMessage,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApiError {
+    #[serde(default)]
+    pub message: String,
+    #[serde(rename = "type", default)]
+    pub error_type: Option<String>,
+    #[serde(default)]
+    pub code: Value,
+}
+
+impl ApiError {
+    // 兼容服务的错误码不统一，同时参考code和type
+    pub fn into_error(self, status: u16) -> ProviderError {
+        let code = self.code.as_str().or(self.error_type.as_deref()).unwrap_or_default();
+        let kind = match code {
+            "invalid_api_key" => ErrorKind::Auth,
+            "insufficient_quota" => ErrorKind::Quota,
+            "rate_limit_exceeded" => ErrorKind::RateLimit,
+            "content_filter" | "content_policy_violation" => ErrorKind::ContentFilter,
+            _ => ErrorKind::from_status(status),
+        };
+        ProviderError::new(kind, format!("HTTP {}：{}", status, self.message))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamChoice {
+    pub delta: ChoiceMessage,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamChunk {
+    #[serde(default)]
+    pub choices: Vec<StreamChoice>,
+    #[serde(default)]
+    pub error: Option<ApiError>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChatResponse {
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    #[serde(default)]
+    pub error: Option<ApiError>,
+}
+
+// 自己发出的消息作为assistant，其他人的消息作为user并带上发送者昵称
+pub fn map_messages(prompt: &ChatPrompt) -> Vec<ChatMessage> {
+    let mut messages = vec![ChatMessage { role: "system", content: prompt.system.clone() }];
+    for item in prompt.history.iter() {
+        if item.sender_name == prompt.nick {
+            messages.push(ChatMessage { role: "assistant", content: item.text.clone() });
+        } else {
+            messages.push(ChatMessage { role: "user", content: format!("{}：{}", item.sender_name, item.text) });
+        }
+    }
+    messages.push(ChatMessage { role: "user", content: prompt.instruction.clone() });
+    messages
+}
+
+// 官方接口需要API Key，本地或自建的兼容服务（vLLM、llama.cpp等）可以不填
+impl<'a> ChatRequest<'a> {
+    pub fn new(model: &'a ModelConfig, prompt: &ChatPrompt, stream: bool) -> Self {
+        ChatRequest {
+            model: model.name.trim(),
+            temperature: model.temperature as f32 / 100.0,
+            stream,
+            messages: map_messages(prompt),
+            stream_options: None,
+            response_format: prompt.json_output.then(|| serde_json::json!({"type": "json_object"})),
+            max_tokens: model.max_tokens,
+            top_p: model.top_p,
+            presence_penalty: model.presence_penalty,
+            frequency_penalty: model.frequency_penalty,
+            stop: &model.stop,
+            options: &model.options,
+        }
+    }
+}
+
+// 流式输出格式为SSE，每个data行携带choices[].delta，以[DONE]结束
+pub fn parse_stream_line(line: &str) -> Result<StreamEvent, ProviderError> {
+    let Some(data) = super::sse_data(line) else { return Ok(StreamEvent::Ignore); };
+    if data == "[DONE]" {
+        return Ok(StreamEvent::Done);
+    }
+    let Ok(chunk) = serde_json::from_str::<StreamChunk>(data) else { return Ok(StreamEvent::Ignore); };
+    if let Some(error) = chunk.error {
+        return Err(error.into_error(200));
+    }
+    let text: String = chunk.choices.iter()
+        .filter_map(|choice| choice.delta.content.as_deref()).collect();
+    Ok(if text.is_empty() { StreamEvent::Ignore } else { StreamEvent::Delta(text) })
+}
+
+pub struct OpenAiProvider {
+    pub default_base: &'static str,
+}
+
+impl OpenAiProvider {
+    pub const OPENAI: OpenAiProvider = OpenAiProvider { default_base: DEFAULT_BASE };
+    pub const LLAMA_CPP: OpenAiProvider = OpenAiProvider { default_base: "http://127.0.0.1:8080" };
+}
+
+impl ReplyProvider for OpenAiProvider {
+    fn default_base(&self) -> &str {
+        self.default_base
+    }
+
+    fn validate(&self, model: &ModelConfig) -> Result<(), ProviderError> {
+        super::check_options(model, TYPED_FIELDS)?;
+        let base_url = super::endpoint(model, self.default_base, "");
+        if base_url == DEFAULT_BASE && model.api_token.is_empty() {
+            Err(ProviderError::new(ErrorKind::Config, "请填写API Key，或填写自建服务的接口地址"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn build_request(&self, client: &Client, model: &ModelConfig, prompt: &ChatPrompt, stream: bool) -> Result<RequestBuilder, ProviderError> {
+        self.validate(model)?;
+        let mut req_body = ChatRequest::new(model, prompt, stream);
+        if stream && super::endpoint(model, self.default_base, "") == DEFAULT_BASE {
+            req_body.stream_options = Some(serde_json::json!({"include_usage": true}));
+        }
+
+        // 兼容填写到/v1为止的接口地址
+        let request_url = if model.base_url.trim().trim_end_matches('/').ends_with("/v1") {
+            super::endpoint(model, self.default_base, "/chat/completions")
+        } else {
+            super::endpoint(model, self.default_base, "/v1/chat/completions")
+        };
+        let mut request = client.post(request_url).json(&req_body);
+        if !model.api_token.is_empty() {
+            request = request.bearer_auth(&model.api_token);
+        }
+        Ok(request)
+    }
+
+    fn parse_response(&self, status: u16, body: &str) -> Result<String, ProviderError> {
+        let resp: ChatResponse = match serde_json::from_str(body) {
+            Ok(resp) => resp,
+            Err(_) if !(200..300).contains(&status) => {
+                return Err(ProviderError::from_status(status, format!("HTTP {}：{}", status, body)));
+            }
+            Err(err) => { return Err(ProviderError::new(ErrorKind::Parse, err.to_string())); }
+        };
+        if let Some(error) = resp.error {
+            return Err(error.into_error(status));
+        }
+        let contents: Vec<String> = resp.choices.into_iter()
+            .filter_map(|choice| choice.message.content).collect();
+        if contents.is_empty() {
+            Err(ProviderError::new(ErrorKind::Parse, "choices为空"))
+        } else {
+            Ok(contents.join("\n"))
+        }
+    }
+
+    fn parse_stream_line(&self, line: &str) -> Result<StreamEvent, ProviderError> {
+        parse_stream_line(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{map_messages, OpenAiProvider};
+    use crate::provider::stub::{sample_prompt, StubServer, FAST_RETRY};
+    use crate::provider::{request_reply, request_reply_stream, ErrorKind, ReplyProvider, Usage};
+    use crate::testutil::test_model;
+
+    #[test]
+    fn test_role_mapping() {
+        let roles: Vec<(&str, String)> = map_messages(&sample_prompt()).into_iter()
+            .map(|message| (message.role, message.content)).collect();
+        assert_eq!(roles[0].0, "system");
+        assert_eq!(roles[1], ("user", String::from("张三：明天一起吃饭吗？")));
+        assert_eq!(roles[2], ("assistant", String::from("好啊，几点？")));
+        assert_eq!(roles[3].0, "user");
+    }
+
+    #[test]
+    fn test_chat_completions() {
+        let server = StubServer::start(vec![(200, r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"好的\n六点吧"}}],"usage":{"prompt_tokens":52,"completion_tokens":9,"total_tokens":61}}"#)]);
+        let reply = server.run(request_reply(&test_model("openai", format!("{}/v1/", server.url())), &sample_prompt(), &FAST_RETRY)).unwrap();
+        assert_eq!(reply.content, "好的\n六点吧");
+        assert_eq!(reply.usage, Usage { prompt_tokens: 52, completion_tokens: 9, total_tokens: 61 });
+
+        let request = server.requests().remove(0);
+        assert_eq!(request.path, "/v1/chat/completions");
+        assert_eq!(request.header("authorization"), Some("Bearer sk-test"));
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["model"], "deepseek-chat");
+        assert_eq!(body["messages"].as_array().unwrap().len(), 4);
+        assert_eq!(body["response_format"]["type"], "json_object");
+    }
+
+    #[test]
+    fn test_stream() {
+        let server = StubServer::start(vec![(200, "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n\
+            data: {\"choices\":[{\"delta\":{\"content\":\"好的\\n六\"}}]}\n\n\
+            data: {\"choices\":[{\"delta\":{\"content\":\"点吧\"}}],\"usage\":null}\n\n\
+            data: {\"choices\":[],\"usage\":{\"prompt_tokens\":52,\"completion_tokens\":9,\"total_tokens\":61}}\n\n\
+            data: [DONE]\n\n")]);
+        let mut deltas = Vec::new();
+        let reply = server.run(request_reply_stream(&test_model("openai", server.url()), &sample_prompt(), &FAST_RETRY,
+            |delta| deltas.push(String::from(delta)))).unwrap();
+        assert_eq!(reply.content, "好的\n六点吧");
+        assert_eq!(reply.usage.total(), 61);
+        assert_eq!(deltas, vec!["好的\n六", "点吧"]);
+        let body: serde_json::Value = serde_json::from_str(&server.requests()[0].body).unwrap();
+        assert_eq!(body["stream"], true);
+        assert!(body.get("stream_options").is_none());
+    }
+
+    #[test]
+    fn test_stream_options() {
+        let request = OpenAiProvider::OPENAI.build_request(&reqwest::Client::new(), &test_model("openai", String::new()), &sample_prompt(), true)
+            .unwrap().build().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    #[test]
+    fn test_stream_ignored() {
+        let server = StubServer::start(vec![(200, r#"{"choices":[{"message":{"content":"好的"}}]}"#)]);
+        let reply = server.run(request_reply_stream(&test_model("openai", server.url()), &sample_prompt(), &FAST_RETRY, |_| {}));
+        assert_eq!(reply.unwrap().content, "好的");
+    }
+
+    #[test]
+    fn test_error_body() {
+        let server = StubServer::start(vec![(401, r#"{"error":{"message":"Incorrect API key","type":"invalid_request_error"}}"#)]);
+        let err = server.run(request_reply(&test_model("openai", server.url()), &sample_prompt(), &FAST_RETRY)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Auth);
+        assert!(err.detail.contains("Incorrect API key"));
+        // 鉴权失败不会重试
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_server_error() {
+        let server = StubServer::start(vec![
+            (503, "Service Unavailable"),
+            (429, r#"{"error":{"message":"Rate limit reached","type":"requests","code":"rate_limit_exceeded"}}"#),
+            (200, r#"{"choices":[{"message":{"content":"好的"}}]}"#)]);
+        let reply = server.run(request_reply(&test_model("openai", server.url()), &sample_prompt(), &FAST_RETRY));
+        assert_eq!(reply.unwrap().content, "好的");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_quota_not_retried() {
+        let server = StubServer::start(vec![
+            (429, r#"{"error":{"message":"You exceeded your current quota","type":"insufficient_quota","code":"insufficient_quota"}}"#)]);
+        let err = server.run(request_reply_stream(&test_model("openai", server.url()), &sample_prompt(), &FAST_RETRY, |_| {})).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Quota);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = test_model("openai", String::new());
+        assert!(OpenAiProvider::OPENAI.validate(&config).is_ok());
+        config.api_token = String::new();
+        assert!(OpenAiProvider::OPENAI.validate(&config).is_err());
+        assert!(OpenAiProvider::LLAMA_CPP.validate(&config).is_ok());
+        config.base_url = String::from("http://127.0.0.1:8000");
+        assert!(OpenAiProvider::OPENAI.validate(&config).is_ok());
+
+        config.options.insert(String::from("repetition_penalty"), serde_json::Value::from(1.1));
+        assert!(OpenAiProvider::OPENAI.validate(&config).is_ok());
+        config.options.insert(String::from("max_tokens"), serde_json::Value::from(512));
+        let err = OpenAiProvider::OPENAI.validate(&config).unwrap_err();
+        assert_eq!((err.kind, err.detail.contains("max_tokens")), (ErrorKind::Config, true));
+    }
+}
@@ -0,0 +1,105 @@
+// 测试用的本地HTTP桩服务，按顺序返回预设的响应，并记录收到的请求
+use std::thread;
+use std::time::Duration;
+use std::future::Future;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::io::{BufRead, BufReader, Read, Write};
+use tokio::runtime::Runtime;
+use crate::testutil::history;
+use super::{ChatPrompt, RetryPolicy};
+
+// 测试中重试不必等待太久
+pub const FAST_RETRY: RetryPolicy = RetryPolicy { max_attempts: 3, base_delay: Duration::from_millis(10) };
+
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl StubRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct StubServer {
+    port: u16,
+    runtime: Runtime,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+}
+
+impl StubServer {
+    pub fn start(responses: Vec<(u16, &str)>) -> StubServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorder = requests.clone();
+        let responses: Vec<(u16, String)> = responses.into_iter()
+            .map(|(status, body)| (status, String::from(body))).collect();
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let Ok((stream, _)) = listener.accept() else { return; };
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                let mut parts = request_line.split_whitespace();
+                let method = String::from(parts.next().unwrap_or_default());
+                let path = String::from(parts.next().unwrap_or_default());
+
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((key, value)) = line.split_once(':') {
+                        headers.push((key.trim().to_lowercase(), String::from(value.trim())));
+                    }
+                }
+                let length = headers.iter().find(|(key, _)| key == "content-length")
+                    .and_then(|(_, value)| value.parse::<usize>().ok()).unwrap_or(0);
+                let mut content = vec![0u8; length];
+                let _ = reader.read_exact(&mut content);
+                recorder.lock().unwrap().push(StubRequest {
+                    method, path, headers,
+                    body: String::from_utf8_lossy(&content).into_owned()
+                });
+
+                let mut stream = reader.into_inner();
+                let _ = write!(stream, "HTTP/1.1 {} STUB\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status, body.len(), body);
+                let _ = stream.flush();
+            }
+        });
+
+        StubServer { port, runtime: Runtime::new().unwrap(), requests }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    pub fn run<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+pub fn sample_prompt() -> ChatPrompt {
+    ChatPrompt {
+        nick: String::from("小明"),
+        bot_name: String::from("智能回复助手"),
+        system: String::from("阅读小明和别人的对话记录，从小明的视角产出5条回复。"),
+        history: history(&[("张三", "明天一起吃饭吗？"), ("小明", "好啊，几点？")]),
+        instruction: String::from("请产出5条回复建议，以JSON格式输出。"),
+        json_output: true,
+    }
+}
@@ -0,0 +1,111 @@
+// 逐步修改选中的建议：记录每一轮修改的版本和要求，多轮修改时模型能看到之前提过的要求
+use crate::provider::ChatPrompt;
+use crate::suggest;
+
+pub const VARIANT_COUNT: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefineRound {
+    pub text: String,
+    pub instruction: String,
+}
+
+// 一次修改的上下文，只对同一个聊天有效
+#[derive(Debug, Clone, Default)]
+pub struct RefineThread {
+    chat: String,
+    rounds: Vec<RefineRound>,
+    // 上一轮给出的修改结果
+    variants: Vec<String>,
+}
+
+impl RefineThread {
+    pub fn new(chat: &str) -> RefineThread {
+        RefineThread { chat: String::from(chat), ..Default::default() }
+    }
+
+    // 选中的是上一轮的修改结果（或再次修改同一版本）时沿用当前的上下文，否则需要重新开始
+    pub fn continues(&self, chat: &str, text: &str) -> bool {
+        self.chat == chat && (self.variants.iter().any(|variant| variant == text)
+            || self.rounds.last().is_some_and(|round| round.text == text))
+    }
+
+    pub fn push(&mut self, text: &str, instruction: &str) -> Result<(), String> {
+        if text.trim().is_empty() {
+            return Err(String::from("请先选择要修改的回复"));
+        } else if instruction.trim().is_empty() {
+            return Err(String::from("请填写修改要求，如更简短、更正式"));
+        }
+        self.rounds.push(RefineRound { text: String::from(text.trim()), instruction: String::from(instruction.trim()) });
+        self.variants.clear();
+        Ok(())
+    }
+
+    pub fn rounds(&self) -> &[RefineRound] {
+        &self.rounds
+    }
+
+    pub fn set_variants(&mut self, variants: Vec<String>) {
+        self.variants = variants;
+    }
+
+    // 最近的聊天记录由调用方按上下文长度截取后填入history
+    pub fn prompt(&self, nick: &str) -> ChatPrompt {
+        let (current, earlier) = self.rounds.split_last().expect("修改记录不能为空");
+        let mut lines = Vec::new();
+        if !earlier.is_empty() {
+            let history: Vec<String> = earlier.iter().enumerate()
+                .map(|(index, round)| format!("{}. 把“{}”按“{}”修改", index + 1, round.text, round.instruction)).collect();
+            lines.push(format!("之前的修改：\n{}", history.join("\n")));
+        }
+        lines.push(format!("当前的版本是：{}\n这次的修改要求是：{}", current.text, current.instruction));
+        ChatPrompt {
+            nick: String::from(nick),
+            bot_name: String::from("回复修改助手"),
+            system: format!("你负责按{}的要求修改一条准备发出的微信回复，保持原意和语言，只做要求的修改。", nick),
+            history: Vec::new(),
+            instruction: format!("{}\n之前提过的要求与这次不冲突时继续保持。请给出{}条不同的修改结果，\
+请以JSON格式输出，格式为{{\"suggestions\": [\"修改1\", \"修改2\"]}}，不要输出JSON之外的任何内容。",
+                lines.join("\n"), VARIANT_COUNT),
+            json_output: true,
+        }
+    }
+}
+
+// 去掉与当前版本相同的结果
+pub fn parse_variants(content: &str, current: &str) -> Vec<String> {
+    suggest::parse_suggestions(content).into_iter().filter(|variant| variant != current.trim()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_variants, RefineThread};
+
+    #[test]
+    fn test_thread() {
+        let mut thread = RefineThread::new("张三");
+        assert!(thread.push("好的，周五见", " ").is_err());
+        thread.push(" 好的，周五见 ", "加上时间").unwrap();
+        let prompt = thread.prompt("小明");
+        assert!(prompt.instruction.starts_with("当前的版本是：好的，周五见\n这次的修改要求是：加上时间\n"));
+        assert!(prompt.instruction.contains("请给出3条不同的修改结果"));
+
+        thread.set_variants(vec![String::from("好的，周五下午三点见"), String::from("好，周五三点见")]);
+        assert!(thread.continues("张三", "好，周五三点见"));
+        assert!(thread.continues("张三", "好的，周五见"));
+        assert!(!thread.continues("李四", "好，周五三点见"));
+        assert!(!thread.continues("张三", "收到"));
+
+        thread.push("好，周五三点见", "更正式").unwrap();
+        assert_eq!(thread.rounds().len(), 2);
+        assert!(thread.prompt("小明").instruction.starts_with(
+            "之前的修改：\n1. 把“好的，周五见”按“加上时间”修改\n当前的版本是：好，周五三点见\n这次的修改要求是：更正式"));
+        assert!(!thread.continues("张三", "好的，周五下午三点见"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let content = r#"{"suggestions": ["好的，周五见。", "好的，我们周五见", "收到，周五见"]}"#;
+        assert_eq!(parse_variants(content, " 好的，周五见"), vec!["好的，我们周五见", "收到，周五见"]);
+    }
+}
@@ -0,0 +1,187 @@
+// 发送前的安全检查：屏蔽词、正则规则、手机号和银行卡号、长度上限。
+// 生成建议时和粘贴到微信前各检查一次，命中后按配置直接拦截或要求确认
+use std::sync::OnceLock;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+const PHONE_PATTERN: &str = r"1[3-9]\d{9}";
+// 16-19位数字，允许每几位之间用空格或横线分隔
+const CARD_PATTERN: &str = r"\d(?:[ -]?\d){15,18}";
+
+static PHONE: OnceLock<Regex> = OnceLock::new();
+static CARD: OnceLock<Regex> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyAction {
+    Block,
+    #[default]
+    Confirm,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SafetyConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // 屏蔽词，不区分大小写
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    // 正则表达式形式的屏蔽规则
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default = "default_true")]
+    pub detect_numbers: bool,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    #[serde(default)]
+    pub action: SafetyAction,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        SafetyConfig {
+            enabled: true,
+            keywords: Vec::new(),
+            patterns: Vec::new(),
+            detect_numbers: true,
+            max_length: None,
+            action: SafetyAction::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SafetyIssue {
+    pub action: SafetyAction,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct SafetyPolicy {
+    config: SafetyConfig,
+    patterns: Vec<(String, Regex)>,
+}
+
+// 前后都不是数字才算一个完整的号码
+fn standalone<'a>(regex: &Regex, text: &'a str) -> Vec<&'a str> {
+    regex.find_iter(text).filter(|found| {
+        !text[..found.start()].ends_with(|c: char| c.is_ascii_digit())
+            && !text[found.end()..].starts_with(|c: char| c.is_ascii_digit())
+    }).map(|found| found.as_str()).collect()
+}
+
+fn luhn(digits: &str) -> bool {
+    let sum: u32 = digits.chars().rev().filter_map(|c| c.to_digit(10)).enumerate().map(|(index, digit)| {
+        if index % 2 == 1 {
+            let doubled = digit * 2;
+            if doubled > 9 { doubled - 9 } else { doubled }
+        } else {
+            digit
+        }
+    }).sum();
+    sum % 10 == 0
+}
+
+// 只保留前三位和后四位
+fn mask(number: &str) -> String {
+    let digits: Vec<char> = number.chars().filter(|c| c.is_ascii_digit()).collect();
+    let head: String = digits[..3].iter().collect();
+    let tail: String = digits[digits.len() - 4..].iter().collect();
+    format!("{}****{}", head, tail)
+}
+
+impl SafetyPolicy {
+    pub fn new(config: &SafetyConfig) -> Result<SafetyPolicy, String> {
+        let patterns = config.patterns.iter().filter(|pattern| !pattern.trim().is_empty()).map(|pattern| {
+            Regex::new(pattern).map(|regex| (pattern.clone(), regex))
+                .map_err(|err| format!("屏蔽规则{}不是有效的正则表达式：{}", pattern, err))
+        }).collect::<Result<Vec<_>, String>>()?;
+        Ok(SafetyPolicy { config: config.clone(), patterns })
+    }
+
+    pub fn check(&self, text: &str) -> Option<SafetyIssue> {
+        if !self.config.enabled {
+            return None;
+        }
+        let mut reasons = Vec::new();
+        let lowercase = text.to_lowercase();
+        for keyword in self.config.keywords.iter().map(|keyword| keyword.trim()).filter(|keyword| !keyword.is_empty()) {
+            if lowercase.contains(&keyword.to_lowercase()) {
+                reasons.push(format!("包含屏蔽词“{}”", keyword));
+            }
+        }
+        for (pattern, regex) in &self.patterns {
+            if regex.is_match(text) {
+                reasons.push(format!("命中屏蔽规则{}", pattern));
+            }
+        }
+        if self.config.detect_numbers {
+            let phone = PHONE.get_or_init(|| Regex::new(PHONE_PATTERN).unwrap());
+            if let Some(number) = standalone(phone, text).first() {
+                reasons.push(format!("包含手机号{}", mask(number)));
+            }
+            let card = CARD.get_or_init(|| Regex::new(CARD_PATTERN).unwrap());
+            if let Some(number) = standalone(card, text).into_iter().find(|number| luhn(number)) {
+                reasons.push(format!("疑似包含银行卡号{}", mask(number)));
+            }
+        }
+        let length = text.chars().count();
+        if let Some(max_length) = self.config.max_length.filter(|max_length| length > *max_length) {
+            reasons.push(format!("长度为{}字，超过了{}字的上限", length, max_length));
+        }
+        if reasons.is_empty() {
+            None
+        } else {
+            Some(SafetyIssue { action: self.config.action, reason: reasons.join("；") })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SafetyAction, SafetyConfig, SafetyPolicy};
+
+    #[test]
+    fn test_keywords_and_patterns() {
+        let config = SafetyConfig {
+            keywords: vec![String::from("保证收益"), String::from("VIP")],
+            patterns: vec![String::from(r"\d+%\s*(回报|利息)")],
+            detect_numbers: false,
+            action: SafetyAction::Block,
+            ..Default::default()
+        };
+        let policy = SafetyPolicy::new(&config).unwrap();
+        assert_eq!(policy.check("好的，明天见"), None);
+        let issue = policy.check("开通vip保证收益，年化20%回报").unwrap();
+        assert_eq!(issue.action, SafetyAction::Block);
+        assert_eq!(issue.reason, "包含屏蔽词“保证收益”；包含屏蔽词“VIP”；命中屏蔽规则\\d+%\\s*(回报|利息)");
+
+        let config = SafetyConfig { patterns: vec![String::from("(")], ..Default::default() };
+        assert!(SafetyPolicy::new(&config).unwrap_err().contains("不是有效的正则表达式"));
+        let config = SafetyConfig { enabled: false, keywords: vec![String::from("好")], ..Default::default() };
+        assert_eq!(SafetyPolicy::new(&config).unwrap().check("好的"), None);
+    }
+
+    #[test]
+    fn test_numbers() {
+        let policy = SafetyPolicy::new(&SafetyConfig::default()).unwrap();
+        assert_eq!(policy.check("我的电话是13812345678").unwrap().reason, "包含手机号138****5678");
+        assert_eq!(policy.check("卡号6222 0200 0000 0000 000，到账告诉我").unwrap().reason, "疑似包含银行卡号622****0000");
+        // 订单号等更长的数字、校验不通过的卡号不算
+        assert_eq!(policy.check("订单号202401381234567890123"), None);
+        assert_eq!(policy.check("6222020000000000001"), None);
+        assert_eq!(policy.check("3点见，带上1000元"), None);
+    }
+
+    #[test]
+    fn test_max_length() {
+        let policy = SafetyPolicy::new(&SafetyConfig { max_length: Some(5), ..Default::default() }).unwrap();
+        assert_eq!(policy.check("好的好的"), None);
+        let issue = policy.check("好的，我马上到").unwrap();
+        assert_eq!((issue.action, issue.reason.as_str()), (SafetyAction::Confirm, "长度为7字，超过了5字的上限"));
+    }
+}
@@ -0,0 +1,361 @@
+// 解析模型返回的回复建议。要求模型输出{"suggestions": [...]}格式的JSON，
+// 对代码块包裹、截断、多余逗号等不规范的输出做容错，仍无法解析时按行拆分
+use serde::{Deserialize, Serialize};
+
+pub const MAX_SUGGESTIONS: usize = 10;
+// 按字数划分短、中、长回复
+const SHORT_MAX_CHARS: usize = 10;
+const LONG_MIN_CHARS: usize = 30;
+
+// 短、中、长三种回复的比例，如2:2:1
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LengthMix {
+    #[serde(default)]
+    pub short: u32,
+    #[serde(default)]
+    pub medium: u32,
+    #[serde(default)]
+    pub long: u32,
+}
+
+impl Default for LengthMix {
+    fn default() -> Self {
+        LengthMix { short: 2, medium: 2, long: 1 }
+    }
+}
+
+impl LengthMix {
+    // 按比例把count条回复分配给短、中、长三档，余数优先分给小数部分大的一档
+    pub fn targets(&self, count: usize) -> [usize; 3] {
+        let weights = [self.short as usize, self.medium as usize, self.long as usize];
+        let total: usize = weights.iter().sum();
+        if total == 0 {
+            return [0, count, 0];
+        }
+        let mut targets = weights.map(|weight| weight * count / total);
+        let mut order = [0, 1, 2];
+        order.sort_by_key(|&index| std::cmp::Reverse(weights[index] * count % total));
+        let assigned: usize = targets.iter().sum();
+        for index in order.into_iter().take(count - assigned) {
+            targets[index] += 1;
+        }
+        targets
+    }
+
+    pub fn describe(&self, count: usize) -> String {
+        let labels = [
+            format!("简短（{}字以内）", SHORT_MAX_CHARS),
+            format!("适中（{}到{}字）", SHORT_MAX_CHARS, LONG_MIN_CHARS),
+            format!("较长（{}字以上）", LONG_MIN_CHARS),
+        ];
+        let parts: Vec<String> = self.targets(count).iter().zip(labels)
+            .filter(|(target, _)| **target > 0).map(|(target, label)| format!("{}条{}", target, label)).collect();
+        parts.join("，")
+    }
+}
+
+fn default_count() -> usize {
+    5
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SuggestionConfig {
+    #[serde(default = "default_count")]
+    pub count: usize,
+    #[serde(default)]
+    pub length_mix: LengthMix,
+}
+
+impl Default for SuggestionConfig {
+    fn default() -> Self {
+        SuggestionConfig { count: default_count(), length_mix: LengthMix::default() }
+    }
+}
+
+impl SuggestionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_SUGGESTIONS).contains(&self.count) {
+            Err(format!("建议条数需要介于1-{}之间", MAX_SUGGESTIONS))
+        } else if self.length_mix.short + self.length_mix.medium + self.length_mix.long == 0 {
+            Err(String::from("长度比例不能全为0"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn length_bucket(text: &str) -> usize {
+    match text.chars().count() {
+        len if len <= SHORT_MAX_CHARS => 0,
+        len if len < LONG_MIN_CHARS => 1,
+        _ => 2,
+    }
+}
+
+// 去重后按长度比例挑选建议，某一档不够时用其他档补齐，保持模型给出的顺序。
+// 已经展示给用户的建议原样排在最前面并占用对应档位的名额，其余的名额再从候选中挑选
+pub fn select_suggestions(shown: &[String], candidates: Vec<String>, config: &SuggestionConfig) -> Vec<String> {
+    let mut selected: Vec<String> = Vec::new();
+    for item in shown {
+        if selected.len() < config.count && !selected.contains(item) {
+            selected.push(item.clone());
+        }
+    }
+    let mut unique: Vec<String> = Vec::new();
+    for candidate in candidates {
+        if !selected.contains(&candidate) && !unique.contains(&candidate) {
+            unique.push(candidate);
+        }
+    }
+    let mut quota = config.length_mix.targets(config.count);
+    for item in &selected {
+        let bucket = length_bucket(item);
+        quota[bucket] = quota[bucket].saturating_sub(1);
+    }
+    let mut remaining = config.count - selected.len();
+    let mut picked: Vec<bool> = unique.iter().map(|item| {
+        let bucket = length_bucket(item);
+        let fits = quota[bucket] > 0 && remaining > 0;
+        if fits {
+            quota[bucket] -= 1;
+            remaining -= 1;
+        }
+        fits
+    }).collect();
+    for item in picked.iter_mut().filter(|item| !**item) {
+        if remaining == 0 {
+            break;
+        }
+        *item = true;
+        remaining -= 1;
+    }
+    selected.extend(unique.into_iter().zip(picked).filter(|(_, picked)| *picked).map(|(item, _)| item));
+    selected
+}
+
+#[derive(Deserialize)]
+struct SuggestionObject {
+    suggestions: Vec<String>,
+}
+
+fn clean_suggestion(line: &str) -> String {
+    String::from(line.trim().trim_end_matches('。'))
+}
+
+fn clean_all(items: Vec<String>) -> Vec<String> {
+    items.iter().map(|item| clean_suggestion(item)).filter(|item| !item.is_empty()).collect()
+}
+
+// 去掉```json ... ```代码块标记，只保留代码块中的内容
+pub fn strip_fence(message: &str) -> &str {
+    let Some(start) = message.find("```") else { return message; };
+    let body = &message[start + 3..];
+    let body = body.find('\n').map_or(body, |pos| &body[pos + 1..]);
+    body.find("```").map_or(body, |end| &body[..end])
+}
+
+fn closing_quote(quote: char) -> Option<char> {
+    match quote {
+        '"' => Some('"'),
+        '\'' => Some('\''),
+        '“' => Some('”'),
+        _ => None
+    }
+}
+
+// 读取一个字符串字面量，返回None表示字符串被截断
+fn read_string(chars: &mut std::str::Chars, close: char) -> Option<String> {
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        if c == close {
+            return Some(value);
+        } else if c == '\\' {
+            match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => {}
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16).ok()?;
+                    value.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                }
+                other => value.push(other),
+            }
+        } else {
+            value.push(c);
+        }
+    }
+    None
+}
+
+// 从第一个'['开始扫描，提取数组中已经完整的字符串元素，第二个返回值表示数组是否已闭合
+fn scan_array(text: &str) -> Option<(Vec<String>, bool)> {
+    let start = text.find('[')?;
+    let mut items = Vec::new();
+    let mut depth = 1;
+    let mut chars = text[start + 1..].chars();
+    while let Some(c) = chars.next() {
+        if let Some(close) = closing_quote(c) {
+            match read_string(&mut chars, close) {
+                Some(value) if depth == 1 => items.push(value),
+                Some(_) => {}
+                None => return Some((items, false)),
+            }
+        } else if c == '[' || c == '{' {
+            depth += 1;
+        } else if c == ']' || c == '}' {
+            depth -= 1;
+            if depth == 0 {
+                return Some((items, true));
+            }
+        }
+    }
+    Some((items, false))
+}
+
+fn split_lines(message: &str) -> Vec<String> {
+    message.split('\n').map(clean_suggestion).filter(|line| !line.is_empty()).collect()
+}
+
+pub fn parse_suggestions(message: &str) -> Vec<String> {
+    let body = strip_fence(message).trim();
+    if let Ok(object) = serde_json::from_str::<SuggestionObject>(body) {
+        return clean_all(object.suggestions);
+    } else if let Ok(items) = serde_json::from_str::<Vec<String>>(body) {
+        return clean_all(items);
+    }
+    match scan_array(body).map(|(items, _)| clean_all(items)) {
+        Some(items) if !items.is_empty() => items,
+        _ => split_lines(message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SplitMode {
+    Json,
+    Lines,
+}
+
+// 流式输出时，JSON格式每读完一个字符串元素就产出一条建议，纯文本格式按换行切分
+#[derive(Debug, Default)]
+pub struct SuggestionSplitter {
+    buffer: String,
+    emitted: usize,
+    mode: Option<SplitMode>,
+}
+
+impl SuggestionSplitter {
+    pub fn push(&mut self, delta: &str) -> Vec<String> {
+        self.buffer.push_str(delta);
+        if self.mode.is_none() {
+            self.mode = match self.buffer.trim_start().chars().next() {
+                Some('[') | Some('{') | Some('`') => Some(SplitMode::Json),
+                Some(_) => Some(SplitMode::Lines),
+                None => None,
+            };
+        }
+        match self.mode {
+            Some(SplitMode::Json) => {
+                let items = scan_array(&self.buffer).map_or(Vec::new(), |(items, _)| items);
+                let total = items.len();
+                let fresh = clean_all(items.into_iter().skip(self.emitted).collect());
+                self.emitted = total;
+                fresh
+            }
+            Some(SplitMode::Lines) => {
+                let mut result = Vec::new();
+                while let Some(pos) = self.buffer.find('\n') {
+                    let line: String = self.buffer.drain(..=pos).collect();
+                    let line = clean_suggestion(&line);
+                    if !line.is_empty() {
+                        result.push(line);
+                    }
+                }
+                result
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_suggestions, select_suggestions, LengthMix, SuggestionConfig, SuggestionSplitter};
+
+    // 实际遇到过的不规范输出
+    const CORPUS: &[(&str, &[&str])] = &[
+        (r#"{"suggestions": ["好的。", "明天见"]}"#, &["好的", "明天见"]),
+        (r#"["好的", "明天见"]"#, &["好的", "明天见"]),
+        ("```json\n{\"suggestions\": [\"好的\", \"明天见\"]}\n```", &["好的", "明天见"]),
+        ("以下是回复建议：\n```\n[\"好的\",\n \"明天见\",\n]\n```\n希望对你有帮助", &["好的", "明天见"]),
+        (r#"{"suggestions": ["好的", "明天见", "没问题，我六点"#, &["好的", "明天见"]),
+        (r#"{"suggestions": ["他说\"好\"", "第一行\n第二行", "你好"]}"#, &["他说\"好\"", "第一行\n第二行", "你好"]),
+        ("['好的', 'It\\'s fine']", &["好的", "It's fine"]),
+        ("[“好的”，“明天见”]", &["好的", "明天见"]),
+        (r#"{"suggestions": ["好的" "明天见"]}"#, &["好的", "明天见"]),
+        ("好的。\n\n\n 明天见，不见不散 \n", &["好的", "明天见，不见不散"]),
+        ("好的 明天见", &["好的 明天见"]),
+        ("[]", &[]),
+    ];
+
+    #[test]
+    fn test_corpus() {
+        for (message, expected) in CORPUS {
+            assert_eq!(parse_suggestions(message), *expected, "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_stream_json() {
+        let mut splitter = SuggestionSplitter::default();
+        assert!(splitter.push("```json\n{\"sugg").is_empty());
+        assert!(splitter.push("estions\": [\"好").is_empty());
+        assert_eq!(splitter.push("的。\", \"明天"), vec!["好的"]);
+        assert_eq!(splitter.push("见\"]}\n```"), vec!["明天见"]);
+        assert!(splitter.push("\n").is_empty());
+    }
+
+    #[test]
+    fn test_stream_lines() {
+        let mut splitter = SuggestionSplitter::default();
+        assert!(splitter.push("好").is_empty());
+        assert_eq!(splitter.push("的。\n\n明天"), vec!["好的"]);
+        assert!(splitter.push("见").is_empty());
+        assert_eq!(splitter.push("\n\n"), vec!["明天见"]);
+    }
+
+    #[test]
+    fn test_length_mix() {
+        let mix = LengthMix::default();
+        assert_eq!(mix.targets(5), [2, 2, 1]);
+        assert_eq!(mix.targets(3), [1, 1, 1]);
+        assert_eq!(mix.targets(1), [1, 0, 0]);
+        assert_eq!(LengthMix { short: 0, medium: 0, long: 1 }.targets(4), [0, 0, 4]);
+        assert_eq!(mix.describe(3), "1条简短（10字以内），1条适中（10到30字），1条较长（30字以上）");
+        assert_eq!(LengthMix { short: 1, medium: 0, long: 0 }.describe(2), "2条简短（10字以内）");
+        assert!(SuggestionConfig { count: 11, ..Default::default() }.validate().is_err());
+        assert!(SuggestionConfig { count: 3, length_mix: LengthMix { short: 0, medium: 0, long: 0 } }.validate().is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let long = "这个方案我觉得整体没有问题，不过预算部分还需要再和财务那边确认一下";
+        let candidates = vec!["好的", "收到", "好的", "没问题，我明天上午过去", "行", long]
+            .into_iter().map(String::from).collect();
+        let config = SuggestionConfig { count: 3, length_mix: LengthMix { short: 1, medium: 1, long: 1 } };
+        assert_eq!(select_suggestions(&[], candidates, &config), vec!["好的", "没问题，我明天上午过去", long]);
+
+        // 某一档不够时按原顺序补齐
+        let candidates = vec!["好的", "收到", "行"].into_iter().map(String::from).collect();
+        assert_eq!(select_suggestions(&[], candidates, &config), vec!["好的", "收到", "行"]);
+        let candidates = vec![String::from("好的")];
+        assert_eq!(select_suggestions(&[], candidates, &SuggestionConfig::default()).len(), 1);
+
+        // 已经展示的建议保留原来的顺序，不够的名额从候选中按档位补齐
+        let shown = vec![String::from("收到"), String::from("好的")];
+        let candidates = vec!["行", "好的", "没问题，我明天上午过去", long].into_iter().map(String::from).collect();
+        assert_eq!(select_suggestions(&shown, candidates, &config), vec!["收到", "好的", "没问题，我明天上午过去"]);
+        let shown: Vec<String> = vec!["收到", "好的", "行", "嗯"].into_iter().map(String::from).collect();
+        assert_eq!(select_suggestions(&shown, vec![String::from(long)], &config), vec!["收到", "好的", "行"]);
+    }
+}
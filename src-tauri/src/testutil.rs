@@ -0,0 +1,42 @@
+// 测试共用的模型配置、聊天记录和临时文件
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use crate::auto::WechatHistory;
+use crate::conf::ModelConfig;
+
+static TEMP_FILES: AtomicU32 = AtomicU32::new(0);
+
+// 各服务商测试用的模型配置，需要时再修改个别字段
+pub fn test_model(provider: &str, base_url: String) -> ModelConfig {
+    let name = match provider {
+        "minimax" => "abab6-chat",
+        "ollama" => "qwen2:7b",
+        "anthropic" => "claude-3-haiku-20240307",
+        "glm" => "glm-4",
+        _ => "deepseek-chat",
+    };
+    ModelConfig {
+        name: String::from(name),
+        provider: String::from(provider),
+        temperature: 70,
+        api_token: String::from("sk-test"),
+        api_group: String::from("group"),
+        base_url,
+        ..Default::default()
+    }
+}
+
+// 按（发送者，内容）依次生成聊天记录
+pub fn history(messages: &[(&str, &str)]) -> Vec<WechatHistory> {
+    messages.iter().map(|(sender, text)| WechatHistory {
+        text: String::from(*text),
+        sender_name: String::from(*sender),
+        sender_type: String::from("USER")
+    }).collect()
+}
+
+// 每次调用都返回不同的临时文件路径，并行执行的测试之间互不覆盖
+pub fn temp_path(name: &str) -> PathBuf {
+    let index = TEMP_FILES.fetch_add(1, Ordering::SeqCst);
+    std::env::temp_dir().join(format!("chat-assistant-{}-{}-{}", std::process::id(), index, name))
+}
@@ -0,0 +1,83 @@
+// 翻译模式：对话不是中文时，把对方最近的消息和每条建议译成中文，方便确认意思后再发送。
+// 建议本身仍按对话的语言生成，翻译在建议生成后单独请求一次
+use serde::{Deserialize, Serialize};
+use crate::auto::WechatHistory;
+use crate::conf::ModelConfig;
+use crate::provider::ChatPrompt;
+use crate::suggest;
+
+pub const NATIVE_LANGUAGE: &str = "中文";
+
+// model为空时使用主模型
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TranslationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub model: Option<ModelConfig>,
+}
+
+impl TranslationConfig {
+    pub fn applies(&self, language: &str) -> bool {
+        self.enabled && language != NATIVE_LANGUAGE
+    }
+}
+
+// 对方在我最后一次发言之后连续发来的消息
+pub fn latest_incoming(nick: &str, history: &[WechatHistory]) -> Vec<String> {
+    let start = history.iter().rposition(|item| item.sender_name == nick).map_or(0, |pos| pos + 1);
+    history[start..].iter().map(|item| item.text.clone()).collect()
+}
+
+pub fn translation_prompt(nick: &str, language: &str, texts: &[String]) -> ChatPrompt {
+    let numbered: Vec<String> = texts.iter().enumerate()
+        .map(|(index, text)| format!("{}. {}", index + 1, text.replace('\n', " "))).collect();
+    ChatPrompt {
+        nick: String::from(nick),
+        bot_name: String::from("翻译助手"),
+        system: format!("你负责把{}聊天中的{}消息逐条翻译成自然、口语化的{}，保留语气和称呼，不要增删内容。",
+            nick, language, NATIVE_LANGUAGE),
+        history: Vec::new(),
+        instruction: format!("请翻译以下{}条消息：\n{}\n请以JSON格式输出，格式为{{\"translations\": [\"译文1\", \"译文2\"]}}，\
+译文的条数和顺序与原文一致，不要输出JSON之外的任何内容。", texts.len(), numbered.join("\n")),
+        json_output: true,
+    }
+}
+
+// 条数对不上时无法与原文逐条对应，视为翻译失败
+pub fn parse_translations(content: &str, expected: usize) -> Option<Vec<String>> {
+    let translations = suggest::parse_suggestions(content);
+    if translations.len() == expected { Some(translations) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{latest_incoming, parse_translations, translation_prompt, TranslationConfig};
+    use crate::testutil::history;
+
+    #[test]
+    fn test_latest_incoming() {
+        let history = history(&[("Tom", "Hi"), ("小明", "Hello"), ("Tom", "Are you free?"), ("Tom", "Let's grab lunch")]);
+        assert_eq!(latest_incoming("小明", &history), vec!["Are you free?", "Let's grab lunch"]);
+        assert_eq!(latest_incoming("小明", &history[..2]), Vec::<String>::new());
+        assert_eq!(latest_incoming("小红", &history[..2]), vec!["Hi", "Hello"]);
+
+        let config = TranslationConfig { enabled: true, model: None };
+        assert!(config.applies("英文"));
+        assert!(!config.applies("中文"));
+        assert!(!TranslationConfig::default().applies("日文"));
+    }
+
+    #[test]
+    fn test_prompt_and_parse() {
+        let texts = vec![String::from("Sure,\nsee you"), String::from("Sounds good")];
+        let prompt = translation_prompt("小明", "英文", &texts);
+        assert!(prompt.system.contains("英文消息逐条翻译成自然、口语化的中文"));
+        assert!(prompt.instruction.starts_with("请翻译以下2条消息：\n1. Sure, see you\n2. Sounds good\n"));
+        assert!(prompt.json_output && prompt.history.is_empty());
+
+        let content = "```json\n{\"translations\": [\"好的，回头见。\", \"听起来不错\"]}\n```";
+        assert_eq!(parse_translations(content, 2).unwrap(), vec!["好的，回头见", "听起来不错"]);
+        assert_eq!(parse_translations(content, 3), None);
+    }
+}
@@ -0,0 +1,161 @@
+// 记录每次调用消耗的token，按日期、厂商和模型汇总，保存在配置目录下的usage.json中
+use std::path::Path;
+use std::collections::BTreeMap;
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use crate::conf::UsageBudget;
+use crate::provider::Usage;
+
+// 只保留最近一段时间的明细，避免文件无限增长
+const KEEP_DAYS: i64 = 400;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageTotals {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, usage: &Usage) {
+        self.calls += 1;
+        self.prompt_tokens += usage.prompt_tokens as u64;
+        self.completion_tokens += usage.completion_tokens as u64;
+        self.total_tokens += usage.total() as u64;
+    }
+
+    fn merge(&mut self, other: &UsageTotals) {
+        self.calls += other.calls;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+// 今日和本月的合计，以及本月各个模型的用量
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct UsageReport {
+    pub today: UsageTotals,
+    pub month: UsageTotals,
+    pub models: BTreeMap<String, UsageTotals>,
+}
+
+// 日期（如2024-05-01） -> 厂商/模型 -> 用量
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct UsageStore {
+    #[serde(default)]
+    days: BTreeMap<String, BTreeMap<String, UsageTotals>>,
+}
+
+fn day_key(day: NaiveDate) -> String {
+    day.format("%Y-%m-%d").to_string()
+}
+
+impl UsageStore {
+    // 文件不存在或已损坏时从零开始统计
+    pub fn load(path: &Path) -> UsageStore {
+        std::fs::read_to_string(path).ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string(self).map_err(|err| err.to_string())?;
+        std::fs::write(path, content).map_err(|err| err.to_string())
+    }
+
+    pub fn record(&mut self, today: NaiveDate, provider: &str, model: &str, usage: &Usage) {
+        let key = format!("{}/{}", provider.trim().to_lowercase(), model.trim());
+        self.days.entry(day_key(today)).or_default().entry(key).or_default().add(usage);
+        self.days = self.days.split_off(&day_key(today - Duration::days(KEEP_DAYS)));
+    }
+
+    pub fn report(&self, today: NaiveDate) -> UsageReport {
+        let today_key = day_key(today);
+        let month_prefix = today.format("%Y-%m-").to_string();
+        let mut report = UsageReport::default();
+        for (day, models) in self.days.iter().filter(|(day, _)| day.starts_with(&month_prefix)) {
+            for (model, totals) in models {
+                report.month.merge(totals);
+                report.models.entry(model.clone()).or_default().merge(totals);
+                if *day == today_key {
+                    report.today.merge(totals);
+                }
+            }
+        }
+        report
+    }
+
+    // 用量达到上限后拒绝继续生成
+    pub fn check_budget(&self, today: NaiveDate, budget: &UsageBudget) -> Result<(), String> {
+        let report = self.report(today);
+        if budget.daily_tokens.is_some_and(|limit| report.today.total_tokens >= limit) {
+            Err(format!("今日已使用{}个token，达到了每日上限，请明天再试或在设置中调整上限", report.today.total_tokens))
+        } else if budget.monthly_tokens.is_some_and(|limit| report.month.total_tokens >= limit) {
+            Err(format!("本月已使用{}个token，达到了每月上限，请下月再试或在设置中调整上限", report.month.total_tokens))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use super::UsageStore;
+    use crate::conf::UsageBudget;
+    use crate::provider::Usage;
+    use crate::testutil::temp_path;
+
+    fn usage(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+        Usage { prompt_tokens, completion_tokens, total_tokens: 0 }
+    }
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_report() {
+        let mut store = UsageStore::default();
+        store.record(day(4, 30), "openai", "gpt-4o-mini", &usage(100, 10));
+        store.record(day(5, 1), "OpenAI", "gpt-4o-mini", &usage(200, 20));
+        store.record(day(5, 2), "minimax", "abab6-chat", &Usage { total_tokens: 50, ..Default::default() });
+        store.record(day(5, 2), "openai", "gpt-4o-mini", &usage(300, 30));
+
+        let report = store.report(day(5, 2));
+        assert_eq!(report.today.calls, 2);
+        assert_eq!(report.today.total_tokens, 380);
+        assert_eq!(report.month.total_tokens, 600);
+        assert_eq!(report.models["openai/gpt-4o-mini"].prompt_tokens, 500);
+        assert_eq!(report.models["minimax/abab6-chat"].total_tokens, 50);
+        assert_eq!(report.models.len(), 2);
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut store = UsageStore::default();
+        store.record(day(5, 1), "openai", "gpt-4o", &usage(900, 100));
+        let budget = UsageBudget { daily_tokens: Some(1000), monthly_tokens: Some(1500) };
+        assert!(store.check_budget(day(5, 1), &budget).unwrap_err().contains("每日上限"));
+        assert!(store.check_budget(day(5, 2), &budget).is_ok());
+        store.record(day(5, 2), "openai", "gpt-4o", &usage(500, 0));
+        assert!(store.check_budget(day(5, 3), &budget).unwrap_err().contains("每月上限"));
+        assert!(store.check_budget(day(6, 1), &UsageBudget::default()).is_ok());
+    }
+
+    #[test]
+    fn test_prune_and_persist() {
+        let mut store = UsageStore::default();
+        store.record(day(1, 1), "ollama", "qwen2", &usage(10, 10));
+        store.record(NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(), "ollama", "qwen2", &usage(10, 10));
+        assert_eq!(store.days.len(), 1);
+
+        let path = temp_path("usage.json");
+        store.save(&path).unwrap();
+        assert_eq!(UsageStore::load(&path).days, store.days);
+        std::fs::write(&path, "{").unwrap();
+        assert!(UsageStore::load(&path).days.is_empty());
+    }
+}